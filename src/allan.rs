@@ -0,0 +1,141 @@
+use fixed::{types::extra::U8, FixedU64};
+use ufmt_float::uFmt_f32;
+
+use crate::stats::isqrt;
+
+/// Number of averaging factors, tau = 1, 2, 4 ... 2^(TAUS - 1) gates.
+const TAUS: usize = 5;
+/// Phase points needed for the second difference at the largest tau.
+const HISTORY: usize = (1 << (TAUS - 1)) * 2 + 1;
+/// Readings further than 1/n of the gate from it are left out.
+const GATE_TOLERANCE: u64 = 16;
+
+/// Overlapping Allan deviation of successive gate readings.
+///
+/// The readings are integrated into phase, as deviations from the first
+/// reading in units of the fixed point LSB, so every tau only needs the
+/// second difference `x[n] - 2 x[n - m] + x[n - 2m]` of the last phase
+/// points. The gates have to be back to back and of equal length, so a
+/// reading stretched by a display refresh ends the run of phase points and
+/// the next one starts a new run, keeping the sums so far. The caller resets
+/// it whenever the counter is restarted.
+pub struct AllanDeviation {
+    reference: FixedU64<U8>,
+    phase: [i64; HISTORY],
+    points: u32,
+    readings: u32,
+    sum_sq: [u64; TAUS],
+    terms: [u32; TAUS],
+}
+
+impl Default for AllanDeviation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllanDeviation {
+    pub const fn new() -> Self {
+        Self {
+            reference: FixedU64::<U8>::ZERO,
+            phase: [0; HISTORY],
+            points: 0,
+            readings: 0,
+            sum_sq: [0; TAUS],
+            terms: [0; TAUS],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Phase `back` points before the last one.
+    fn phase_back(&self, back: u32) -> i64 {
+        self.phase[((self.points - 1 - back) as usize) % HISTORY]
+    }
+
+    /// Adds a reading taken over `interval_micros`. If that is too far from
+    /// the `gate_micros` the others were taken over, the reading is left out
+    /// and the phase starts over, as it cannot be integrated across the gap.
+    pub fn add(&mut self, freq: FixedU64<U8>, interval_micros: FixedU64<U8>, gate_micros: u32) {
+        let gate = FixedU64::<U8>::from(gate_micros);
+        let error = if interval_micros > gate {
+            interval_micros - gate
+        } else {
+            gate - interval_micros
+        };
+        if error > gate / GATE_TOLERANCE {
+            self.points = 0;
+            return;
+        }
+
+        if self.points == 0 {
+            // the second differences do not depend on the reference, so the
+            // first one is kept across the runs
+            if self.readings == 0 {
+                self.reference = freq;
+            }
+            self.phase[0] = 0;
+            self.points = 1;
+        }
+        self.readings += 1;
+
+        let deviation = freq.to_bits() as i64 - self.reference.to_bits() as i64;
+        let phase = self.phase_back(0) + deviation;
+
+        self.phase[(self.points as usize) % HISTORY] = phase;
+        self.points += 1;
+
+        for k in 0..TAUS {
+            let m: u32 = 1 << k;
+            if self.points <= 2 * m {
+                break;
+            }
+
+            let second_difference =
+                (phase - 2 * self.phase_back(m) + self.phase_back(2 * m)).unsigned_abs();
+
+            self.sum_sq[k] =
+                self.sum_sq[k].saturating_add(second_difference.saturating_mul(second_difference));
+            self.terms[k] += 1;
+        }
+    }
+
+    /// Allan deviation at `m` gates, in ppb of the first reading.
+    fn adev_ppb(&self, k: usize) -> Option<f32> {
+        if self.terms[k] == 0 || self.reference == 0 {
+            return None;
+        }
+
+        let m = 1_u64 << k;
+
+        // sigma^2 = sum / (2 m^2 N), scaled by 2^16 so the square root keeps
+        // eight more fractional bits.
+        let variance =
+            (self.sum_sq[k] / self.terms[k] as u64).saturating_mul(1 << 16) / (2 * m * m);
+        let sigma = isqrt(variance) as f32 / 65536.0;
+
+        Some(sigma / self.reference.to_num::<f32>() * 1e9)
+    }
+
+    /// Writes one line for every tau with enough data, `gate_ms` being the
+    /// length of a single reading.
+    pub fn report<W: ufmt::uWrite>(&self, w: &mut W, gate_ms: u32) -> Result<(), W::Error> {
+        ufmt::uwriteln!(w, "Allan deviation, {} readings", self.readings)?;
+
+        for k in 0..TAUS {
+            if let Some(adev) = self.adev_ppb(k) {
+                ufmt::uwriteln!(
+                    w,
+                    "tau={} ms adev={} ppb n={}",
+                    gate_ms << k,
+                    uFmt_f32::Three(adev),
+                    self.terms[k],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::tcounter::{CountMode, TCounter};
+
+/// Picks the counting method of a `TCounter` based on the last reading.
+///
+/// Direct counting has an absolute resolution of one count per gate, so it
+/// is only good for high frequencies, while reciprocal counting has a
+/// constant relative resolution but needs an interrupt for every input edge.
+/// Above `crossover_hz` we count directly and below it we time the periods,
+/// with a band of `hysteresis_hz` around the crossover where we keep
+/// whatever method we are already using.
+pub struct AutoRange<'a> {
+    counter: &'a TCounter,
+    crossover_hz: u32,
+    hysteresis_hz: u32,
+}
+
+impl<'a> AutoRange<'a> {
+    /// Edge rate above which the capture interrupt takes all of the CPU.
+    const MAX_CAPTURE_HZ: u64 = 50_000;
+
+    pub fn new(counter: &'a TCounter, crossover_hz: u32, hysteresis_hz: u32) -> Self {
+        Self {
+            counter,
+            crossover_hz,
+            hysteresis_hz,
+        }
+    }
+
+    /// Switches the counter to the best method for `freq_hz`.
+    ///
+    /// Returns `true` if the counter has been reconfigured, in which case the
+    /// previous snapshots cannot be compared with the new ones.
+    pub fn update(&self, freq_hz: FixedU64<U8>) -> bool {
+        let upper = FixedU64::<U8>::from(self.crossover_hz + self.hysteresis_hz);
+        let lower = FixedU64::<U8>::from(self.crossover_hz - self.hysteresis_hz);
+
+        let next_mode = match self.counter.mode() {
+            CountMode::Direct if freq_hz < lower => CountMode::Reciprocal,
+            CountMode::Reciprocal if freq_hz > upper => CountMode::Direct,
+            mode => mode,
+        };
+
+        if next_mode == self.counter.mode() {
+            return false;
+        }
+
+        self.counter.set_mode(next_mode);
+        true
+    }
+
+    /// Switches back to direct counting if `edges` captured over the last
+    /// `interval_micros` are more than the capture interrupt can keep up with.
+    ///
+    /// Meant for every pass of the main loop, not just the closed gates: the
+    /// capture interrupt outranks the one behind `micros()`, so an input that
+    /// got too fast keeps the gate from closing and `update` from ever seeing
+    /// it. Returns `true` if the counter has been reconfigured.
+    pub fn check_capture_rate(&self, edges: u32, interval_micros: u32) -> bool {
+        // the clock may have all but stopped, so give it at least a ms
+        let interval_micros = u64::from(interval_micros.max(1_000));
+
+        if self.counter.mode() == CountMode::Direct
+            || u64::from(edges) * 1_000_000 <= Self::MAX_CAPTURE_HZ * interval_micros
+        {
+            return false;
+        }
+
+        self.counter.set_mode(CountMode::Direct);
+        true
+    }
+}
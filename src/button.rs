@@ -0,0 +1,40 @@
+use embedded_hal::digital::v2::InputPin;
+
+/// A push button between a pulled-up input and ground.
+///
+/// The pin is only sampled from the main loop, so contact bounce is filtered
+/// by ignoring changes that come sooner than `DEBOUNCE_MICROS` after the
+/// last accepted one.
+pub struct Button<P> {
+    pin: P,
+    down: bool,
+    last_change_micros: u32,
+}
+
+impl<P: InputPin> Button<P> {
+    const DEBOUNCE_MICROS: u32 = 30_000;
+
+    pub fn new(pin: P, now_micros: u32) -> Self {
+        Self {
+            pin,
+            down: false,
+            last_change_micros: now_micros,
+        }
+    }
+
+    /// Returns true once per press, as soon as the button goes down.
+    pub fn pressed(&mut self, now_micros: u32) -> bool {
+        let down = matches!(self.pin.is_low(), Ok(true));
+
+        if down == self.down
+            || now_micros.wrapping_sub(self.last_change_micros) < Self::DEBOUNCE_MICROS
+        {
+            return false;
+        }
+
+        self.down = down;
+        self.last_change_micros = now_micros;
+
+        down
+    }
+}
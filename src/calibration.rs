@@ -0,0 +1,165 @@
+use arduino_hal::clock::Clock;
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::eeprom::Eeprom;
+use crate::prescaler::ExternalPrescaler;
+use crate::tcounter::{CountMode, TCounter};
+use crate::timerclock::TClock;
+
+const RECORD_ADDRESS: u16 = 0;
+const RECORD_VERSION: u8 = 1;
+
+/// Number of one second gates averaged when calibrating against a reference.
+const REFERENCE_GATES: u32 = 5;
+const REFERENCE_GATE_MICROS: u32 = 1_000_000;
+/// Counting a slower reference for 5 s would only resolve 1 / (5 s * f), so
+/// below this we time its periods instead.
+const RECIPROCAL_BELOW_HZ: u32 = 10_000;
+const CPU_CYCLES_PER_MICRO: i64 = arduino_hal::DefaultClock::FREQ as i64 / 1_000_000;
+/// Anything above this is a wrong nominal value or a bad signal, not a clock error.
+const MAX_CORRECTION_PPM: i64 = 50_000;
+
+/// Linear correction of the time base, in parts per million.
+///
+/// A positive value means the board clock runs slow, so the readings have to
+/// be scaled up by that amount.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Calibration {
+    ppm: i32,
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Self { ppm: 0 };
+
+    pub const fn from_ppm(ppm: i32) -> Self {
+        Self { ppm }
+    }
+
+    pub const fn ppm(self) -> i32 {
+        self.ppm
+    }
+
+    /// Measures a reference signal of known frequency on D5 and computes the
+    /// correction that makes the readings match it.
+    ///
+    /// `nominal_hz` is the frequency of the reference itself, so with an
+    /// external prescaler it is the one in front of it. A reference slower
+    /// than `RECIPROCAL_BELOW_HZ` at the pin has its periods timed on D8, so
+    /// it has to be wired to both.
+    ///
+    /// Blocks for `REFERENCE_GATES` seconds and leaves the counter in direct
+    /// mode. Returns `None` if there is no signal or the correction is
+    /// implausibly large.
+    pub fn from_reference(
+        counter: &TCounter,
+        clock: &TClock,
+        prescaler: ExternalPrescaler,
+        nominal_hz: u32,
+    ) -> Option<Self> {
+        let pin_hz = nominal_hz / u32::from(prescaler.ratio());
+        let measured = if pin_hz < RECIPROCAL_BELOW_HZ {
+            time_periods(counter, clock)
+        } else {
+            count_edges(counter, clock)
+        };
+        counter.set_mode(CountMode::Direct);
+
+        let (edges, cycles) = measured?;
+
+        // the counts of the reference, not those of the pin
+        let counts = edges * prescaler.ratio() as i64;
+
+        // nominal / measured - 1, in ppm
+        let ppm = (nominal_hz as i64 * cycles - counts * CPU_CYCLES_PER_MICRO * 1_000_000)
+            / (counts * CPU_CYCLES_PER_MICRO);
+
+        if ppm.abs() > MAX_CORRECTION_PPM {
+            return None;
+        }
+
+        Some(Self::from_ppm(ppm as i32))
+    }
+
+    /// Loads the calibration record, if there is a valid one.
+    pub fn load(eeprom: &Eeprom) -> Option<Self> {
+        let mut payload = [0_u8; 4];
+
+        if eeprom.read_record(RECORD_ADDRESS, RECORD_VERSION, &mut payload) {
+            Some(Self::from_ppm(i32::from_le_bytes(payload)))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(self, eeprom: &Eeprom) {
+        eeprom.write_record(RECORD_ADDRESS, RECORD_VERSION, &self.ppm.to_le_bytes());
+    }
+
+    /// Applies the correction to the counts of a reading.
+    pub fn apply(self, counts: FixedU64<U8>) -> FixedU64<U8> {
+        let factor = (1_000_000 + self.ppm).max(0) as u64;
+
+        counts * factor / 1_000_000
+    }
+}
+
+/// Counts the reference on T1 over the reference gates, returns the edges and
+/// the CPU cycles they took.
+fn count_edges(counter: &TCounter, clock: &TClock) -> Option<(i64, i64)> {
+    counter.set_mode(CountMode::Direct);
+
+    let mut total_counts: i64 = 0;
+    let mut total_micros: i64 = 0;
+
+    let mut last_count = counter.count();
+    let mut last_micros = clock.micros();
+
+    for _ in 0..REFERENCE_GATES {
+        while clock.micros().wrapping_sub(last_micros) < REFERENCE_GATE_MICROS {}
+
+        let count = counter.count();
+        let micros = clock.micros();
+
+        total_counts += count.delta_since(last_count) as i64;
+        total_micros += micros.wrapping_sub(last_micros) as i64;
+
+        last_count = count;
+        last_micros = micros;
+    }
+
+    if total_counts == 0 {
+        return None;
+    }
+
+    Some((total_counts, total_micros * CPU_CYCLES_PER_MICRO))
+}
+
+/// Times whole periods of the reference on ICP1 for as long as the reference
+/// gates, returns the periods and the CPU cycles they took.
+fn time_periods(counter: &TCounter, clock: &TClock) -> Option<(i64, i64)> {
+    counter.set_mode(CountMode::Reciprocal);
+
+    let start_micros = clock.micros();
+    let mut first = None;
+    let mut last = counter.edge_capture();
+
+    while clock.micros().wrapping_sub(start_micros) < REFERENCE_GATES * REFERENCE_GATE_MICROS {
+        last = counter.edge_capture();
+
+        // the first edge only starts the first period
+        if first.is_none() && last.edges > 0 {
+            first = Some(last);
+        }
+    }
+
+    let first = first?;
+    let periods = last.edges.wrapping_sub(first.edges);
+    if periods == 0 {
+        return None;
+    }
+
+    Some((
+        periods as i64,
+        last.timestamp.wrapping_sub(first.timestamp) as i64,
+    ))
+}
@@ -0,0 +1,307 @@
+// Line based command console over the serial port.
+//
+// Bytes are collected by the USART receive interrupt, so nothing gets lost
+// while the main loop is busy talking to the display.
+
+use core::cell::RefCell;
+use core::str::FromStr;
+
+use avr_device::interrupt::Mutex;
+use fixed::{types::extra::U8, FixedU64};
+use heapless::spsc::Queue;
+use heapless::String;
+
+use crate::flow::FlowMeter;
+use crate::gate::GateTime;
+use crate::hold::HoldMode;
+use crate::lcmeter::{self, LcComponent, LcStep};
+use crate::mains;
+use crate::prescaler::ExternalPrescaler;
+use crate::tachometer::Tachometer;
+use crate::tcounter::CounterInput;
+use crate::tuner::Tuner;
+use crate::ui::DisplayMode;
+
+/// Longest PPS gate we accept, in seconds.
+const MAX_PPS_SECONDS: u32 = 1_000;
+
+/// Longest signal timeout we accept, in milliseconds.
+const MAX_TIMEOUT_MS: u32 = 600_000;
+
+static RX_QUEUE: Mutex<RefCell<Queue<u8, 32>>> = Mutex::new(RefCell::new(Queue::new()));
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `gate <ms>`: set the gate time
+    Gate(GateTime),
+    /// `cal <hz>`: calibrate against a reference of the given frequency on D5
+    Calibrate(u32),
+    /// `pps <s>`: gate on the 1PPS input for the given seconds, 0 to disable
+    Pps(u32),
+    /// `mode <name>`: choose what the display shows
+    Mode(DisplayMode),
+    /// `stats`: print the statistics
+    Stats,
+    /// `stats reset`: restart the statistics
+    StatsReset,
+    /// `adev`: print the Allan deviation
+    Adev,
+    /// `adev reset`: restart the Allan deviation
+    AdevReset,
+    /// `timeout <ms>`: how long without edges before declaring the signal lost
+    Timeout(u32),
+    /// `prescale <n>`: division ratio of the external prescaler, 1 for none
+    Prescale(ExternalPrescaler),
+    /// `ppr <n>`: pulses per revolution of the tachometer sensor
+    PulsesPerRev(Tachometer),
+    /// `kfactor <n>`: pulses per litre of the flow sensor
+    KFactor(u32),
+    /// `flow reset`: restart the dispensed volume
+    FlowReset,
+    /// `a4 <hz>`: reference pitch of the tuner
+    A4(Tuner),
+    /// `lc zero` and `lc ref <pF>`: calibrate the LC meter
+    LcCalibrate(LcStep),
+    /// `lc c|l`: what the LC meter is measuring
+    LcComponent(LcComponent),
+    /// `mains 50|60`: nominal grid frequency
+    MainsNominal(u32),
+    /// `mains alarm <mHz>`: deviation that raises the alarm, 0 to disable
+    MainsAlarm(u32),
+    /// `mains reset`: start a new session
+    MainsReset,
+    /// `nominal <hz>`: reference of the relative mode, decimals allowed
+    Nominal(FixedU64<U8>),
+    /// `nominal capture`: take the next reading as the nominal
+    NominalCapture,
+    /// `limit low <hz>`: lower frequency limit, 0 to disable
+    LimitLow(Option<FixedU64<U8>>),
+    /// `limit high <hz>`: upper frequency limit, 0 to disable
+    LimitHigh(Option<FixedU64<U8>>),
+    /// `limit hyst <hz>`: how far back inside a reading has to come to pass
+    LimitHysteresis(FixedU64<U8>),
+    /// `buzzer on|off`: sound the buzzer while a limit is exceeded
+    Buzzer(bool),
+    /// `hold off|on|min|max`: freeze the display or show the peak readings
+    Hold(HoldMode),
+    /// `hold reset`: restart the min/max peaks
+    HoldReset,
+    /// `edge rising|falling|cpu`: what the counter counts
+    Edge(CounterInput),
+    /// `total`: print the totalizer count
+    Total,
+    /// `total start`: start or resume totalizing
+    TotalStart,
+    /// `total stop`: pause totalizing
+    TotalStop,
+    /// `total reset`: clear the totalizer count
+    TotalReset,
+    /// `preset <n>`: count down from n and fire the batch output, 0 to disable
+    Preset(u32),
+    /// Anything we could not make sense of
+    Invalid,
+}
+
+impl Command {
+    fn parse(line: &str) -> Self {
+        let mut words = line.split_ascii_whitespace();
+
+        let command = match words.next() {
+            Some("gate") => words
+                .next()
+                .and_then(|ms| u32::from_str(ms).ok())
+                .and_then(GateTime::from_ms)
+                .map(Command::Gate),
+            Some("cal") => words
+                .next()
+                .and_then(|hz| u32::from_str(hz).ok())
+                .filter(|hz| *hz > 0)
+                .map(Command::Calibrate),
+            Some("pps") => words
+                .next()
+                .and_then(|seconds| u32::from_str(seconds).ok())
+                .filter(|seconds| *seconds <= MAX_PPS_SECONDS)
+                .map(Command::Pps),
+            Some("mode") => words
+                .next()
+                .and_then(DisplayMode::from_name)
+                .map(Command::Mode),
+            Some("stats") => match words.next() {
+                None => Some(Command::Stats),
+                Some("reset") => Some(Command::StatsReset),
+                _ => None,
+            },
+            Some("adev") => match words.next() {
+                None => Some(Command::Adev),
+                Some("reset") => Some(Command::AdevReset),
+                _ => None,
+            },
+            Some("timeout") => words
+                .next()
+                .and_then(|ms| u32::from_str(ms).ok())
+                .filter(|ms| (1..=MAX_TIMEOUT_MS).contains(ms))
+                .map(Command::Timeout),
+            Some("prescale") => words
+                .next()
+                .and_then(|ratio| u16::from_str(ratio).ok())
+                .and_then(ExternalPrescaler::from_ratio)
+                .map(Command::Prescale),
+            Some("ppr") => words
+                .next()
+                .and_then(|pulses| u16::from_str(pulses).ok())
+                .and_then(Tachometer::from_pulses_per_rev)
+                .map(Command::PulsesPerRev),
+            Some("kfactor") => words
+                .next()
+                .and_then(|k_factor| u32::from_str(k_factor).ok())
+                .filter(|k_factor| FlowMeter::is_valid_k_factor(*k_factor))
+                .map(Command::KFactor),
+            Some("flow") => match words.next() {
+                Some("reset") => Some(Command::FlowReset),
+                _ => None,
+            },
+            Some("a4") => words
+                .next()
+                .and_then(|hz| u16::from_str(hz).ok())
+                .and_then(Tuner::from_a4_hz)
+                .map(Command::A4),
+            Some("lc") => match words.next() {
+                Some("zero") => Some(Command::LcCalibrate(LcStep::Zero)),
+                Some("ref") => words
+                    .next()
+                    .and_then(|pf| u32::from_str(pf).ok())
+                    .filter(|pf| (1..=lcmeter::MAX_REFERENCE_PF).contains(pf))
+                    .map(|pf| Command::LcCalibrate(LcStep::Reference(pf))),
+                Some("c") => Some(Command::LcComponent(LcComponent::Capacitor)),
+                Some("l") => Some(Command::LcComponent(LcComponent::Inductor)),
+                _ => None,
+            },
+            Some("mains") => match words.next() {
+                Some("50") => Some(Command::MainsNominal(50)),
+                Some("60") => Some(Command::MainsNominal(60)),
+                Some("alarm") => words
+                    .next()
+                    .and_then(|mhz| u32::from_str(mhz).ok())
+                    .filter(|mhz| *mhz <= mains::MAX_ALARM_MHZ)
+                    .map(Command::MainsAlarm),
+                Some("reset") => Some(Command::MainsReset),
+                _ => None,
+            },
+            Some("nominal") => match words.next() {
+                Some("capture") => Some(Command::NominalCapture),
+                Some(hz) => FixedU64::<U8>::from_str(hz)
+                    .ok()
+                    .filter(|hz| *hz > 0)
+                    .map(Command::Nominal),
+                None => None,
+            },
+            Some("limit") => {
+                let kind = words.next();
+                let hz = words
+                    .next()
+                    .and_then(|hz| FixedU64::<U8>::from_str(hz).ok());
+                let limit = hz.map(|hz| if hz == 0 { None } else { Some(hz) });
+
+                match kind {
+                    Some("low") => limit.map(Command::LimitLow),
+                    Some("high") => limit.map(Command::LimitHigh),
+                    Some("hyst") => hz.map(Command::LimitHysteresis),
+                    _ => None,
+                }
+            }
+            Some("buzzer") => match words.next() {
+                Some("on") => Some(Command::Buzzer(true)),
+                Some("off") => Some(Command::Buzzer(false)),
+                _ => None,
+            },
+            Some("hold") => match words.next() {
+                Some("reset") => Some(Command::HoldReset),
+                Some(name) => HoldMode::from_name(name).map(Command::Hold),
+                None => None,
+            },
+            Some("edge") => match words.next() {
+                Some("rising") => Some(Command::Edge(CounterInput::RisingEdge)),
+                Some("falling") => Some(Command::Edge(CounterInput::FallingEdge)),
+                Some("cpu") => Some(Command::Edge(CounterInput::CpuClock)),
+                _ => None,
+            },
+            Some("total") => match words.next() {
+                None => Some(Command::Total),
+                Some("start") => Some(Command::TotalStart),
+                Some("stop") => Some(Command::TotalStop),
+                Some("reset") => Some(Command::TotalReset),
+                _ => None,
+            },
+            Some("preset") => words
+                .next()
+                .and_then(|counts| u32::from_str(counts).ok())
+                .map(Command::Preset),
+            _ => None,
+        };
+
+        match (command, words.next()) {
+            (Some(command), None) => command,
+            _ => Command::Invalid,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CommandReader {
+    line: String<32>,
+    overflow: bool,
+}
+
+impl CommandReader {
+    /// Creates the reader, the USART receive interrupt has to be enabled with
+    /// `serial.listen(Event::RxComplete)` for it to get any data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the next complete command received, if any.
+    pub fn poll(&mut self) -> Option<Command> {
+        while let Some(byte) =
+            avr_device::interrupt::free(|cs| RX_QUEUE.borrow(cs).borrow_mut().dequeue())
+        {
+            match byte {
+                b'\r' | b'\n' => {
+                    if self.line.is_empty() && !self.overflow {
+                        continue;
+                    }
+
+                    let command = if self.overflow {
+                        Command::Invalid
+                    } else {
+                        Command::parse(self.line.as_str())
+                    };
+
+                    self.line.clear();
+                    self.overflow = false;
+
+                    return Some(command);
+                }
+                _ => {
+                    if self.line.push(byte as char).is_err() {
+                        self.overflow = true;
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn USART_RX() {
+    // SAFETY: reading UDR0 only pops the received byte, the transmit side
+    // owned by the serial driver is not affected.
+    let usart = unsafe { &*arduino_hal::pac::USART0::ptr() };
+    let byte = usart.udr0.read().bits();
+
+    avr_device::interrupt::free(|cs| {
+        // if the main loop does not keep up we just drop the byte
+        let _ = RX_QUEUE.borrow(cs).borrow_mut().enqueue(byte);
+    });
+}
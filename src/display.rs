@@ -198,6 +198,24 @@ impl Command for SetDDRAMAddress {
     }
 }
 
+struct SetCGRAMAddress {
+    address: u8,
+}
+impl Command for SetCGRAMAddress {
+    fn rw() -> RW {
+        RW::Write
+    }
+
+    fn rs() -> RS {
+        RS::Disabled
+    }
+
+    fn payload(self) -> u8 {
+        // NOTE: if address is larger than 6bit-max we truncate it.
+        0b01000000u8 | (self.address & 0x3F)
+    }
+}
+
 struct WriteToDDRAM {
     data: u8,
 }
@@ -274,6 +292,36 @@ impl<'a> I2cDisplay<'a> {
         Ok(())
     }
 
+    pub fn write_lines(
+        &mut self,
+        first: String<16>,
+        second: String<16>,
+    ) -> Result<(), arduino_hal::i2c::Error> {
+        self.move_cursor(0)?;
+        self.write_line(first)?;
+        self.move_cursor(16)?;
+        self.write_line(second)
+    }
+
+    /// Loads a 5x8 custom character into one of the 8 CGRAM slots, it is
+    /// then shown by writing the slot number as a character.
+    pub fn define_char(
+        &mut self,
+        slot: u8,
+        pattern: [u8; 8],
+    ) -> Result<(), arduino_hal::i2c::Error> {
+        self.write_cmd_imp(SetCGRAMAddress {
+            address: (slot & 0x7) << 3,
+        })?;
+
+        // data writes go to CGRAM until a DDRAM address is set again
+        for row in pattern {
+            self.write_cmd_imp(WriteToDDRAM { data: row })?;
+        }
+
+        self.write_cmd_imp(SetDDRAMAddress::default())
+    }
+
     pub fn read_busy_and_AC(&mut self) -> Result<(bool, u8), arduino_hal::i2c::Error> {
         let mut read_buffer: [u8; 2] = [0; 2];
         let (one, two) = read_buffer.split_at_mut(1);
@@ -0,0 +1,92 @@
+// Works for ATMega328p
+
+use arduino_hal::pac::EEPROM;
+
+/// CRC-8 with polynomial 0x07, used to validate the stored records.
+///
+/// Takes the CRC of the previous data, so it can be computed piecewise.
+fn crc8(mut crc: u8, data: &[u8]) -> u8 {
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+pub struct Eeprom {
+    /// The EEPROM registers, gives this instance unique control over them.
+    eeprom: EEPROM,
+}
+
+impl Eeprom {
+    pub fn new(eeprom: EEPROM) -> Self {
+        Self { eeprom }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        // wait for any write in progress
+        while self.eeprom.eecr.read().eepe().bit_is_set() {}
+
+        self.eeprom.eear.write(|w| unsafe { w.bits(address) });
+        self.eeprom.eecr.write(|w| w.eere().set_bit());
+
+        self.eeprom.eedr.read().bits()
+    }
+
+    pub fn write_byte(&self, address: u16, data: u8) {
+        // spare the cell a write cycle if it already holds the value
+        if self.read_byte(address) == data {
+            return;
+        }
+
+        avr_device::interrupt::free(|_cs| {
+            self.eeprom.eear.write(|w| unsafe { w.bits(address) });
+            self.eeprom.eedr.write(|w| unsafe { w.bits(data) });
+
+            // EEPE has to be set within four clock cycles from EEMPE
+            self.eeprom.eecr.write(|w| w.eempe().set_bit());
+            self.eeprom
+                .eecr
+                .write(|w| w.eempe().set_bit().eepe().set_bit());
+        });
+    }
+
+    /// Reads a record saved with `write_record` into `payload`.
+    ///
+    /// Returns `false` if there is no valid record at `address`, i.e. the
+    /// version or the length do not match or the CRC is wrong.
+    pub fn read_record(&self, address: u16, version: u8, payload: &mut [u8]) -> bool {
+        if self.read_byte(address) != version || self.read_byte(address + 1) != payload.len() as u8
+        {
+            return false;
+        }
+
+        for (offset, byte) in payload.iter_mut().enumerate() {
+            *byte = self.read_byte(address + 2 + offset as u16);
+        }
+
+        let crc = self.read_byte(address + 2 + payload.len() as u16);
+
+        crc == crc8(crc8(0, &[version, payload.len() as u8]), payload)
+    }
+
+    /// Saves `payload` at `address` as `[version, length, payload.., crc]`.
+    pub fn write_record(&self, address: u16, version: u8, payload: &[u8]) {
+        self.write_byte(address, version);
+        self.write_byte(address + 1, payload.len() as u8);
+
+        for (offset, byte) in payload.iter().enumerate() {
+            self.write_byte(address + 2 + offset as u16, *byte);
+        }
+
+        let crc = crc8(crc8(0, &[version, payload.len() as u8]), payload);
+        self.write_byte(address + 2 + payload.len() as u16, crc);
+    }
+}
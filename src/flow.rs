@@ -0,0 +1,79 @@
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::eeprom::Eeprom;
+
+const RECORD_ADDRESS: u16 = 32;
+const RECORD_VERSION: u8 = 1;
+
+/// Largest K-factor we accept, in pulses per litre.
+pub const MAX_K_FACTOR: u32 = 100_000;
+
+/// Flow rate and dispensed volume out of a pulse output flow sensor.
+///
+/// The K-factor is the number of pulses per litre found in the sensor
+/// datasheet, e.g. 450 for the common YF-S201.
+pub struct FlowMeter {
+    k_factor: u32,
+    pulses: u64,
+}
+
+impl FlowMeter {
+    pub const DEFAULT_K_FACTOR: u32 = 450;
+
+    pub const fn new(k_factor: u32) -> Self {
+        Self {
+            k_factor,
+            pulses: 0,
+        }
+    }
+
+    pub const fn is_valid_k_factor(k_factor: u32) -> bool {
+        k_factor > 0 && k_factor <= MAX_K_FACTOR
+    }
+
+    pub fn k_factor(&self) -> u32 {
+        self.k_factor
+    }
+
+    /// Sets the K-factor, the volume so far is converted with the new one.
+    pub fn set_k_factor(&mut self, k_factor: u32) {
+        self.k_factor = k_factor;
+    }
+
+    /// Loads the K-factor record, if there is a valid one.
+    pub fn load(eeprom: &Eeprom) -> Option<Self> {
+        let mut payload = [0_u8; 4];
+
+        if !eeprom.read_record(RECORD_ADDRESS, RECORD_VERSION, &mut payload) {
+            return None;
+        }
+
+        let k_factor = u32::from_le_bytes(payload);
+        Self::is_valid_k_factor(k_factor).then_some(Self::new(k_factor))
+    }
+
+    /// Stores the K-factor, the volume is not persisted.
+    pub fn store(&self, eeprom: &Eeprom) {
+        eeprom.write_record(RECORD_ADDRESS, RECORD_VERSION, &self.k_factor.to_le_bytes());
+    }
+
+    /// Adds the pulses of a gate to the volume.
+    pub fn add(&mut self, pulses: u64) {
+        self.pulses = self.pulses.saturating_add(pulses);
+    }
+
+    /// Restarts the volume from zero.
+    pub fn reset(&mut self) {
+        self.pulses = 0;
+    }
+
+    /// Flow rate of `counts` pulses in `interval_micros`, in litres per minute.
+    pub fn rate_lpm(&self, counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> FixedU64<U8> {
+        counts * 60_000_000 / interval_micros / self.k_factor as u64
+    }
+
+    /// The volume since the last reset, in litres.
+    pub fn volume_litres(&self) -> FixedU64<U8> {
+        FixedU64::<U8>::from_num(self.pulses) / self.k_factor as u64
+    }
+}
@@ -1,6 +1,8 @@
 use fixed::{types::extra::U8, FixedU64};
 use heapless::String;
 
+use crate::units::{Scaled, TimeUnit, Unit};
+
 fn digit_to_char(digit: usize) -> Option<char> {
     const DIGITS: [char; 10] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
 
@@ -10,39 +12,132 @@ fn digit_to_char(digit: usize) -> Option<char> {
     }
 }
 
-pub fn format_freq(freq: FixedU64<U8>) -> String<7> {
-    let powers_of_ten: [FixedU64<U8>; 4] = [
-        FixedU64::<U8>::from(1_u32),
-        FixedU64::<U8>::from(10_u32),
-        FixedU64::<U8>::from(100_u32),
-        FixedU64::<U8>::from(1000_u32),
-    ];
+/// Formats an integer, there is no `ToString` without an allocator.
+pub fn format_u32(value: u32) -> String<10> {
+    let mut result = String::<10>::new();
+    let digits = count_digits(value.into());
+
+    for idx in (0..digits).rev() {
+        let digit = (value / 10_u32.pow(idx) % 10) as usize;
+        if let Some(c) = digit_to_char(digit) {
+            result.push(c).unwrap();
+        }
+    }
 
-    let mut result = String::<7>::new();
-    let mut leading_zero = true;
+    result
+}
 
-    for idx in (0..=3).rev() {
-        let digit_idx = ((freq / powers_of_ten[idx]) % 10).floor().to_num();
+/// Formats an integer with its sign, a '+' included, for deviations.
+pub fn format_signed(value: i32) -> String<11> {
+    let mut result = String::<11>::new();
 
-        if leading_zero && digit_idx == 0 {
-            continue;
-        } else {
-            leading_zero = false;
-        }
+    result.push(if value < 0 { '-' } else { '+' }).unwrap();
+    result
+        .push_str(format_u32(value.unsigned_abs()).as_str())
+        .unwrap();
 
-        if let Some(c) = digit_to_char(digit_idx) {
+    result
+}
+
+/// Formats a 64-bit integer, for the counts that do not fit a `u32`.
+pub fn format_u64(value: u64) -> String<20> {
+    let mut result = String::<20>::new();
+    let digits = count_digits(value);
+
+    for idx in (0..digits).rev() {
+        let digit = (value / 10_u64.pow(idx) % 10) as usize;
+        if let Some(c) = digit_to_char(digit) {
             result.push(c).unwrap();
         }
     }
 
+    result
+}
+
+/// Number of decimal digits needed to write `value`.
+pub fn count_digits(mut value: u64) -> u32 {
+    let mut digits = 1;
+
+    while value >= 10 {
+        value /= 10;
+        digits += 1;
+    }
+
+    digits
+}
+
+/// Formats `freq` with only the digits the measurement can resolve.
+///
+/// `significant_digits` is how many digits the reading is good for, e.g. the
+/// number of digits of the counts collected during the gate. The decimals
+/// are what is left after the integer part, up to the two that the 8
+/// fractional bits can resolve.
+pub fn format_freq(freq: FixedU64<U8>, significant_digits: u32) -> String<8> {
+    format_scaled(Scaled::from_fixed(freq), significant_digits)
+}
+
+/// Like `format_freq`, for a value already scaled to its unit, with up to
+/// three of its decimals.
+pub fn format_scaled(scaled: Scaled, significant_digits: u32) -> String<8> {
+    let mut result = String::<8>::new();
+
+    // at most four integer digits, the display has no room for more
+    let integer = scaled.integer() % 10_000;
+    let integer_digits = if integer == 0 {
+        0
+    } else {
+        count_digits(integer)
+    };
+    result.push_str(format_u64(integer).as_str()).unwrap();
+
+    let decimals = significant_digits
+        .saturating_sub(integer_digits)
+        .min(scaled.decimals)
+        .min(3);
+    if decimals == 0 {
+        return result;
+    }
+
     result.push('.').unwrap();
 
-    for idx in 1..=3 {
-        let digit_idx = ((freq * powers_of_ten[idx]) % 10).floor().to_num();
-        if let Some(c) = digit_to_char(digit_idx) {
+    for idx in 1..=decimals {
+        let digit = (scaled.value / 10_u64.pow(scaled.decimals - idx) % 10) as usize;
+        if let Some(c) = digit_to_char(digit) {
             result.push(c).unwrap();
         }
     }
 
     result
 }
+
+/// Like `format_freq`, but for values that are never scaled to a larger
+/// unit, so the integer part can take more than four digits.
+pub fn format_wide(value: FixedU64<U8>, significant_digits: u32) -> String<20> {
+    if value < 10_000 {
+        let mut result = String::<20>::new();
+        let _ = result.push_str(format_freq(value, significant_digits).as_str());
+        return result;
+    }
+
+    format_u64(value.to_num())
+}
+
+/// Formats a value in Hz with the most fitting unit, see `format_freq`.
+pub fn format_hz(hz: FixedU64<U8>, significant_digits: u32) -> (String<8>, &'static str) {
+    let unit = Unit::for_hz(hz);
+
+    (
+        format_scaled(unit.scale_hz(hz), significant_digits),
+        unit.symbol(),
+    )
+}
+
+/// Formats a time in microseconds with the most fitting unit, see `format_freq`.
+pub fn format_time(micros: FixedU64<U8>, significant_digits: u32) -> (String<8>, &'static str) {
+    let unit = TimeUnit::for_micros(micros);
+
+    (
+        format_freq(unit.scale_micros(micros), significant_digits),
+        unit.symbol(),
+    )
+}
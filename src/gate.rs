@@ -0,0 +1,26 @@
+/// The gate time of a measurement.
+///
+/// Only values of the 1-2-5 sequence between 10 ms and 10 s are allowed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GateTime {
+    ms: u32,
+}
+
+impl GateTime {
+    pub const _200_MS: Self = Self { ms: 200 };
+
+    pub const fn from_ms(ms: u32) -> Option<Self> {
+        match ms {
+            10 | 20 | 50 | 100 | 200 | 500 | 1_000 | 2_000 | 5_000 | 10_000 => Some(Self { ms }),
+            _ => None,
+        }
+    }
+
+    pub const fn as_ms(self) -> u32 {
+        self.ms
+    }
+
+    pub const fn as_micros(self) -> u32 {
+        self.ms * 1_000
+    }
+}
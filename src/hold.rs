@@ -0,0 +1,136 @@
+use fixed::{types::extra::U8, FixedU64};
+
+/// Multimeter style hold functions.
+///
+/// Min and max hold apply to the frequency reading. Hold freezes whatever
+/// the display is showing. The readings keep being taken either way, so
+/// going back to `Off` shows a fresh one right away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HoldMode {
+    Off,
+    Hold,
+    Min,
+    Max,
+}
+
+impl HoldMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "on" => Some(Self::Hold),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Hold => "on",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+
+    /// What replaces the method in the top right corner of the display.
+    pub const fn tag(self) -> &'static str {
+        match self {
+            Self::Off => "",
+            Self::Hold => "HLD",
+            Self::Min => "MIN",
+            Self::Max => "MAX",
+        }
+    }
+
+    /// The mode the hold button switches to.
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Hold,
+            Self::Hold => Self::Min,
+            Self::Min => Self::Max,
+            Self::Max => Self::Off,
+        }
+    }
+}
+
+/// A frequency reading with the significant digits it is good for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Reading {
+    pub hz: FixedU64<U8>,
+    pub significant_digits: u32,
+}
+
+/// Keeps the readings the hold functions show.
+pub struct PeakHold {
+    mode: HoldMode,
+    last: Option<Reading>,
+    held: Option<Reading>,
+    min: Option<Reading>,
+    max: Option<Reading>,
+}
+
+impl Default for PeakHold {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeakHold {
+    pub const fn new() -> Self {
+        Self {
+            mode: HoldMode::Off,
+            last: None,
+            held: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn mode(&self) -> HoldMode {
+        self.mode
+    }
+
+    /// Switching to hold freezes the last reading, switching to min or max
+    /// hold from anything else than the other one restarts the peaks.
+    pub fn set_mode(&mut self, mode: HoldMode) {
+        match mode {
+            HoldMode::Hold => self.held = self.last,
+            HoldMode::Min | HoldMode::Max
+                if !matches!(self.mode, HoldMode::Min | HoldMode::Max) =>
+            {
+                self.reset()
+            }
+            _ => {}
+        }
+
+        self.mode = mode;
+    }
+
+    /// Forgets the peaks so far.
+    pub fn reset(&mut self) {
+        self.min = None;
+        self.max = None;
+    }
+
+    pub fn update(&mut self, reading: Reading) {
+        self.last = Some(reading);
+
+        if self.min.map_or(true, |min| reading.hz < min.hz) {
+            self.min = Some(reading);
+        }
+        if self.max.map_or(true, |max| reading.hz > max.hz) {
+            self.max = Some(reading);
+        }
+    }
+
+    /// The reading to show instead of the live one, if any.
+    pub fn reading(&self) -> Option<Reading> {
+        match self.mode {
+            HoldMode::Off => None,
+            HoldMode::Hold => self.held,
+            HoldMode::Min => self.min,
+            HoldMode::Max => self.max,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use core::f32::consts::PI;
+
+use fixed::{types::extra::U8, FixedU64};
+
+/// Largest reference capacitor we accept, in pF.
+pub const MAX_REFERENCE_PF: u32 = 1_000_000;
+
+/// What is connected to the oscillator: a capacitor goes in parallel with
+/// its capacitor, an inductor in series with its inductor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LcComponent {
+    Capacitor,
+    Inductor,
+}
+
+/// A calibration step, taken on the next reading after it is requested.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LcStep {
+    /// Nothing connected, measure the bare oscillator
+    Zero,
+    /// The reference capacitor of the given pF connected in parallel
+    Reference(u32),
+}
+
+/// Capacitance and inductance from the frequency shift of an LC oscillator.
+///
+/// Zeroing gives f1 = 1 / (2π √(L1 C1)), adding the reference capacitor
+/// gives f2, from which C1 = Cref f2² / (f1² - f2²) and then L1. An unknown
+/// is then (f1² / f3² - 1) times C1 or L1.
+///
+/// The values span from pF to µF, too many decades for our fixed point, so
+/// this is done in `f32`.
+pub struct LcMeter {
+    component: LcComponent,
+    pending: Option<LcStep>,
+    f1_hz: Option<f32>,
+    c1_farads: Option<f32>,
+}
+
+impl Default for LcMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LcMeter {
+    pub const fn new() -> Self {
+        Self {
+            component: LcComponent::Capacitor,
+            pending: None,
+            f1_hz: None,
+            c1_farads: None,
+        }
+    }
+
+    pub fn component(&self) -> LcComponent {
+        self.component
+    }
+
+    pub fn set_component(&mut self, component: LcComponent) {
+        self.component = component;
+    }
+
+    pub fn is_calibrated(&self) -> bool {
+        self.f1_hz.is_some() && self.c1_farads.is_some()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Takes `step` on the next reading.
+    pub fn request(&mut self, step: LcStep) {
+        self.pending = Some(step);
+    }
+
+    /// Feeds a reading to the pending calibration step, if any. Returns the
+    /// step once taken, as an error if the reading made no sense for it.
+    pub fn update(&mut self, hz: FixedU64<U8>) -> Option<Result<LcStep, LcStep>> {
+        let step = self.pending.take()?;
+        let hz = hz.to_num::<f32>();
+
+        let done = match (step, self.f1_hz) {
+            (LcStep::Zero, _) => {
+                self.f1_hz = Some(hz);
+                true
+            }
+            // the reference capacitor can only lower the frequency
+            (LcStep::Reference(pf), Some(f1)) if hz > 0.0 && hz < f1 => {
+                let f2_sq = hz * hz;
+                self.c1_farads = Some(pf as f32 * 1e-12 * f2_sq / (f1 * f1 - f2_sq));
+                true
+            }
+            (LcStep::Reference(_), _) => false,
+        };
+
+        Some(if done { Ok(step) } else { Err(step) })
+    }
+
+    /// The unknown component, in farads or henries, from the reading `hz`.
+    pub fn value(&self, hz: FixedU64<U8>) -> Option<f32> {
+        let (f1, c1) = (self.f1_hz?, self.c1_farads?);
+        let f3 = hz.to_num::<f32>();
+
+        if f3 <= 0.0 {
+            return None;
+        }
+
+        let shift = f1 * f1 / (f3 * f3) - 1.0;
+
+        Some(match self.component {
+            LcComponent::Capacitor => shift * c1,
+            LcComponent::Inductor => shift / (4.0 * PI * PI * f1 * f1 * c1),
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use fixed::{types::extra::U8, FixedU64};
+
+/// Where the last reading falls with respect to the limits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitState {
+    Pass,
+    Under,
+    Over,
+}
+
+/// High and low frequency limits, for pass/fail testing.
+///
+/// Once a limit is crossed the reading has to come back inside by the
+/// hysteresis before it passes again, so a board right at a limit does not
+/// make the output chatter.
+pub struct Limits {
+    low_hz: Option<FixedU64<U8>>,
+    high_hz: Option<FixedU64<U8>>,
+    hysteresis_hz: FixedU64<U8>,
+    state: LimitState,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Limits {
+    pub const fn new() -> Self {
+        Self {
+            low_hz: None,
+            high_hz: None,
+            hysteresis_hz: FixedU64::<U8>::ZERO,
+            state: LimitState::Pass,
+        }
+    }
+
+    /// Whether there is any limit to check.
+    pub fn is_enabled(&self) -> bool {
+        self.low_hz.is_some() || self.high_hz.is_some()
+    }
+
+    pub fn state(&self) -> LimitState {
+        self.state
+    }
+
+    pub fn set_low_hz(&mut self, low_hz: Option<FixedU64<U8>>) {
+        self.low_hz = low_hz;
+        self.state = LimitState::Pass;
+    }
+
+    pub fn set_high_hz(&mut self, high_hz: Option<FixedU64<U8>>) {
+        self.high_hz = high_hz;
+        self.state = LimitState::Pass;
+    }
+
+    pub fn set_hysteresis_hz(&mut self, hysteresis_hz: FixedU64<U8>) {
+        self.hysteresis_hz = hysteresis_hz;
+    }
+
+    /// Checks a reading against the limits, returns the new state when it
+    /// changes.
+    pub fn update(&mut self, hz: FixedU64<U8>) -> Option<LimitState> {
+        let over = |margin: FixedU64<U8>| {
+            self.high_hz
+                .map_or(false, |high| hz > high.saturating_sub(margin))
+        };
+        let under = |margin: FixedU64<U8>| {
+            self.low_hz
+                .map_or(false, |low| hz < low.saturating_add(margin))
+        };
+
+        // leaving a limit takes the hysteresis, crossing one does not
+        let state = match self.state {
+            LimitState::Over if over(self.hysteresis_hz) => LimitState::Over,
+            LimitState::Under if under(self.hysteresis_hz) => LimitState::Under,
+            _ if over(FixedU64::<U8>::ZERO) => LimitState::Over,
+            _ if under(FixedU64::<U8>::ZERO) => LimitState::Under,
+            _ => LimitState::Pass,
+        };
+
+        if state == self.state {
+            return None;
+        }
+
+        self.state = state;
+        Some(state)
+    }
+}
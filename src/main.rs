@@ -2,40 +2,96 @@
 #![no_main]
 #![feature(abi_avr_interrupt)]
 #![feature(unwrap_infallible)]
+#![feature(const_option)]
 
 use core::str::FromStr;
 
 use panic_halt as _;
 
+mod allan;
+mod autorange;
+mod button;
+mod calibration;
+mod commands;
 mod display;
+mod eeprom;
+mod flow;
 mod format_utils;
+mod gate;
+mod hold;
+mod lcmeter;
+mod limits;
+mod mains;
+mod pps;
+mod prescaler;
+mod relative;
+mod signal;
+mod snapshot;
+mod stats;
+mod tachometer;
 mod tcounter;
+mod timerclock;
+mod totalizer;
+mod tuner;
+mod ui;
+mod units;
 
+use arduino_hal::clock::Clock;
 use fixed::{types::extra::U8, FixedU64};
-use ufmt_float::uFmt_f32;
 
+use allan::AllanDeviation;
+use autorange::AutoRange;
+use button::Button;
+use calibration::Calibration;
+use commands::{Command, CommandReader};
 use display::I2cDisplay;
+use eeprom::Eeprom;
+use flow::FlowMeter;
+use gate::GateTime;
 use heapless::String;
-use tcounter::TCounter;
-
-fn correct_frequency_counts(counts: u32) -> u32 {
-    counts - counts * 4 / 100
+use hold::{HoldMode, PeakHold, Reading};
+use lcmeter::{LcComponent, LcMeter, LcStep};
+use limits::{LimitState, Limits};
+use mains::MainsMonitor;
+use pps::PpsGate;
+use prescaler::ExternalPrescaler;
+use relative::Relative;
+use signal::{SignalMonitor, SignalState};
+use snapshot::Snapshot;
+use stats::Statistics;
+use tachometer::Tachometer;
+use tcounter::{CountMode, CounterInput, TCounter};
+use timerclock::{Resolution, TClock};
+use totalizer::Totalizer;
+use tuner::Tuner;
+use ui::DisplayMode;
+use units::{TimeUnitSelector, UnitSelector};
+
+const CPU_CYCLES_PER_MICRO: u64 = arduino_hal::DefaultClock::FREQ as u64 / 1_000_000;
+
+fn frequency_hz(counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> FixedU64<U8> {
+    // A long PPS gate behind a prescaler takes the counts past 2^56 / 10^6,
+    // so the product is worked out on the raw bits in 128 bits
+    let bits =
+        (u128::from(counts.to_bits()) << 8) * 1_000_000 / u128::from(interval_micros.to_bits());
+
+    FixedU64::<U8>::from_bits(bits as u64)
 }
 
-fn get_frequency(
-    mut counts: FixedU64<U8>,
-    interval_micros: FixedU64<U8>,
-) -> (FixedU64<U8>, &'static str) {
-    const UNITS: [&str; 3] = ["MHz", "kHz", "Hz"];
-
-    let mut idx = 0;
-
-    while counts < interval_micros {
-        counts *= 1000;
-        idx += 1;
+fn input_label(input: CounterInput) -> &'static str {
+    match input {
+        CounterInput::RisingEdge => "rising edges",
+        CounterInput::FallingEdge => "falling edges",
+        CounterInput::CpuClock => "the CPU clock",
     }
+}
 
-    (counts / interval_micros, UNITS[idx])
+fn method_label(mode: CountMode) -> &'static str {
+    match mode {
+        CountMode::Direct => "DIR",
+        CountMode::Reciprocal => "REC",
+        CountMode::PulseWidth => "PUL",
+    }
 }
 
 #[arduino_hal::entry]
@@ -51,9 +107,38 @@ fn main() -> ! {
     );
 
     let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    serial.listen(arduino_hal::hal::usart::Event::RxComplete);
 
     // Signal clock counter section
-    let counter = TCounter::new(dp.TC1, true);
+    let counter = TCounter::new(dp.TC1, CounterInput::RisingEdge);
+
+    let eeprom = Eeprom::new(dp.EEPROM);
+
+    // Optional GPS 1PPS input for the gate, on INT0
+    let _pps_pin = pins.d2.into_floating_input();
+    let pps = PpsGate::new(dp.EXINT);
+
+    // Time base for the gate, every counter snapshot gets timestamped with it
+    let clock = TClock::new(dp.TC0, Resolution::_1_MS)
+        .ok()
+        .expect("Failed to configure TC0 clock");
+
+    // Push button to ground on D4, cycles through the display modes
+    let mut mode_button = Button::new(pins.d4.into_pull_up_input(), clock.micros());
+
+    // Totalizer controls: start/stop on D3, reset on D7. D6 goes high once
+    // the preset count has been reached, e.g. to close a valve.
+    let mut start_button = Button::new(pins.d3.into_pull_up_input(), clock.micros());
+    let mut reset_button = Button::new(pins.d7.into_pull_up_input(), clock.micros());
+    let mut batch_output = pins.d6.into_output();
+
+    // Pass/fail testing: D9 goes high while the reading is outside the
+    // limits, e.g. for a fail LED or relay, and D10 drives an active buzzer.
+    let mut fail_output = pins.d9.into_output();
+    let mut buzzer = pins.d10.into_output();
+
+    // Push button to ground on A0, cycles through the hold functions
+    let mut hold_button = Button::new(pins.a0.into_pull_up_input(), clock.micros());
 
     // Display section
     let mut display = I2cDisplay::new(&mut i2c, 0x27u8);
@@ -68,6 +153,33 @@ fn main() -> ! {
         .unwrap();
     ufmt::uwriteln!(&mut serial, "Display initialized").unwrap();
 
+    for (slot, pattern) in ui::CUSTOM_CHARS {
+        display
+            .define_char(slot, pattern)
+            .expect("Failed to define custom characters");
+    }
+
+    let mut calibration = Calibration::load(&eeprom).unwrap_or_else(|| {
+        ufmt::uwriteln!(&mut serial, "No valid calibration, using identity").unwrap();
+        Calibration::IDENTITY
+    });
+    ufmt::uwriteln!(&mut serial, "Calibration: {} ppm", calibration.ppm()).unwrap();
+
+    let mut prescaler = ExternalPrescaler::load(&eeprom).unwrap_or(ExternalPrescaler::NONE);
+    ufmt::uwriteln!(&mut serial, "External prescaler: /{}", prescaler.ratio()).unwrap();
+
+    let mut flow =
+        FlowMeter::load(&eeprom).unwrap_or_else(|| FlowMeter::new(FlowMeter::DEFAULT_K_FACTOR));
+    ufmt::uwriteln!(&mut serial, "Flow K-factor: {} pulses/L", flow.k_factor()).unwrap();
+
+    let mut tachometer = Tachometer::load(&eeprom).unwrap_or(Tachometer::ONE_PULSE);
+    ufmt::uwriteln!(
+        &mut serial,
+        "Tachometer: {} pulses/rev",
+        tachometer.pulses_per_rev()
+    )
+    .unwrap();
+
     display
         .write_line(String::<16>::from_str("Initialized").unwrap())
         .unwrap();
@@ -82,68 +194,794 @@ fn main() -> ! {
     //From this point on an interrupt can happen
     unsafe { avr_device::interrupt::enable() };
 
-    const DISPLAY_WRITE_TIME_MILLIS: u32 = 42;
-    let delay_in_ms: u16 = 200;
-    let mut micros_elapsed: FixedU64<U8> =
-        FixedU64::<U8>::from(1000 * (delay_in_ms as u32 + DISPLAY_WRITE_TIME_MILLIS));
-    // let micros_elapsed: FixedU64<U8> = FixedU64::<U8>::from(1000 * (delay_in_ms as u32));
-
-    let mut last_clock_cycles_meas: u32 = 0;
+    let mut gate = GateTime::_200_MS;
+    // Gate length in PPS pulses, when the PPS defines the gate
+    let mut pps_seconds: Option<u32> = None;
+    let mut commands = CommandReader::new();
+    let mut display_mode = DisplayMode::Frequency;
+    let mut stats = Statistics::new();
+    let mut allan = AllanDeviation::new();
+    let mut unit_selector = UnitSelector::new();
+    let mut period_unit_selector = TimeUnitSelector::new();
+    let mut totalizer = Totalizer::new();
+    let mut tuner = Tuner::DEFAULT;
+    let mut lc_meter = LcMeter::new();
+    let mut mains = MainsMonitor::new(50);
+    let mut relative = Relative::new();
+    let mut limits = Limits::new();
+    let mut buzzer_enabled = false;
+    let mut hold = PeakHold::new();
+
+    // Readings with fewer counts than this do not count as a signal
+    const MIN_SIGNAL_COUNTS: u64 = 1;
+    const DEFAULT_SIGNAL_TIMEOUT_MS: u32 = 2_000;
+    // The PPS comes every second whatever the gate, so this is a missed pulse
+    const PPS_TIMEOUT_MICROS: u32 = 2_000_000;
+
+    // The statistics are good for more digits than a single reading
+    const STATS_SIGNIFICANT_DIGITS: u32 = 7;
+    // leaves room for the duty cycle on the same line
+    const DUTY_SIGNIFICANT_DIGITS: u32 = 4;
+    // Pulse widths take an interrupt on both edges, faster than this the
+    // capture ISR leaves too little time for the main loop. Below the lower
+    // bound the duty cycle is measured again.
+    const DUTY_MAX_HZ: u32 = 20_000;
+    const DUTY_RESUME_HZ: u32 = 16_000;
+
+    // Refreshing the display takes ~52 ms on a 328p, so we only do it every
+    // now and then to keep it from stretching the short gates.
+    const DISPLAY_REFRESH_MICROS: u32 = 250_000;
+
+    // Direct counting resolves ~5 Hz at a 200 ms gate, below the crossover
+    // we switch to reciprocal counting. The input has to be wired to both D5
+    // and D8 for this to work.
+    const CROSSOVER_HZ: u32 = 10_000;
+    const HYSTERESIS_HZ: u32 = 2_000;
+    let autorange = AutoRange::new(&counter, CROSSOVER_HZ, HYSTERESIS_HZ);
+
+    let mut last = Snapshot::take(&counter, &clock, &pps);
+    let mut last_refresh_micros: u32 = last.micros;
+    let mut signal = SignalMonitor::new(DEFAULT_SIGNAL_TIMEOUT_MS, last.micros);
+    let mut refresh_count: u32 = 0;
+    // Rate at the pin of the last reading, while there is a signal
+    let mut last_pin_hz: Option<FixedU64<U8>> = None;
+    // When the PPS count last moved, to notice the pulses stopping
+    let mut last_pps_pulses: u32 = 0;
+    let mut last_pps_micros: u32 = last.micros;
 
     loop {
-        let clock_cycles_meas = correct_frequency_counts(counter.clock_cycles());
-        let delta_clock_cycles: FixedU64<U8> =
-            FixedU64::<U8>::from(clock_cycles_meas - last_clock_cycles_meas);
-
-        let (freq, f_unit) = get_frequency(delta_clock_cycles, micros_elapsed);
-
-        let d_disp = uFmt_f32::Three(delta_clock_cycles.to_num::<f32>());
-        let f_disp = uFmt_f32::Three(freq.to_num::<f32>());
-
-        // just this adds ~10 ms to the whole loop
-        // ufmt::uwriteln!(
-        //     &mut serial,
-        //     "measured {} clock cycles, freq = {} {}",
-        //     d_disp,
-        //     f_disp,
-        //     f_unit,
-        // )
-        // .unwrap();
-
-        // This takes approximately ~52 ms on a 328p
-
-        // move cursor to second line
-        display.move_cursor(16).expect("Move cursor failed"); // ~8 ms
-        let f_str = format_utils::format_freq(freq); // ~2 ms
-        let mut second_line = String::<16>::new();
-
-        second_line
-            .push_str(" ")
-            .and_then(|_| second_line.push_str(f_str.as_str()))
-            .and_then(|_| second_line.push(' '))
-            .and_then(|_| second_line.push_str(f_unit))
-            .or_else(|_| {
-                ufmt::uwriteln!(&mut serial, "Failed to format line");
-                Ok::<(), ()>(())
-            })
+        let mut new_mode = None;
+        let mut new_hold = None;
+
+        if let Some(command) = commands.poll() {
+            match command {
+                Command::Gate(new_gate) => {
+                    gate = new_gate;
+                    // the readings so far have a different tau
+                    allan.reset();
+                    ufmt::uwriteln!(&mut serial, "Gate time set to {} ms", gate.as_ms()).unwrap();
+                }
+                Command::Calibrate(nominal_hz) => {
+                    ufmt::uwriteln!(&mut serial, "Calibrating against {} Hz", nominal_hz).unwrap();
+
+                    display.move_cursor(16).expect("Move cursor failed");
+                    display
+                        .write_line(String::<16>::from_str(" Calibrating... ").unwrap())
+                        .expect("Failed to write to display");
+
+                    match Calibration::from_reference(&counter, &clock, prescaler, nominal_hz) {
+                        Some(new_calibration) => {
+                            calibration = new_calibration;
+                            calibration.store(&eeprom);
+                            ufmt::uwriteln!(
+                                &mut serial,
+                                "Calibration stored: {} ppm",
+                                calibration.ppm()
+                            )
+                            .unwrap();
+                        }
+                        None => {
+                            ufmt::uwriteln!(
+                                &mut serial,
+                                "Calibration failed, check the reference signal"
+                            )
+                            .unwrap();
+                        }
+                    }
+
+                    // The counter has been restarted, take fresh snapshots
+                    last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+                    totalizer.rebase(last.count);
+                    allan.reset();
+                }
+                Command::Pps(seconds) => {
+                    pps_seconds = if seconds == 0 { None } else { Some(seconds) };
+                    pps.set_enabled(pps_seconds.is_some());
+                    last_pps_micros = clock.micros();
+                    allan.reset();
+
+                    // The PPS gate only makes sense with direct counting
+                    counter.set_mode(CountMode::Direct);
+                    last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+                    totalizer.rebase(last.count);
+
+                    match pps_seconds {
+                        Some(seconds) => {
+                            ufmt::uwriteln!(&mut serial, "PPS gate of {} s", seconds).unwrap()
+                        }
+                        None => ufmt::uwriteln!(&mut serial, "PPS gate disabled").unwrap(),
+                    }
+                }
+                Command::Mode(mode) => {
+                    new_mode = Some(mode);
+                }
+                Command::Stats => {
+                    stats.report(&mut serial).unwrap();
+                }
+                Command::StatsReset => {
+                    stats.reset();
+                    ufmt::uwriteln!(&mut serial, "Statistics reset").unwrap();
+                }
+                Command::Adev => {
+                    let gate_ms = match pps_seconds {
+                        Some(seconds) => seconds * 1_000,
+                        None => gate.as_ms(),
+                    };
+                    allan.report(&mut serial, gate_ms).unwrap();
+                }
+                Command::AdevReset => {
+                    allan.reset();
+                    ufmt::uwriteln!(&mut serial, "Allan deviation reset").unwrap();
+                }
+                Command::Timeout(timeout_ms) => {
+                    signal.set_timeout_ms(timeout_ms);
+                    ufmt::uwriteln!(
+                        &mut serial,
+                        "Signal timeout set to {} ms",
+                        signal.timeout_ms()
+                    )
+                    .unwrap();
+                }
+                Command::Prescale(new_prescaler) => {
+                    prescaler = new_prescaler;
+                    prescaler.store(&eeprom);
+                    // the readings so far were taken with another ratio
+                    stats.reset();
+                    allan.reset();
+                    ufmt::uwriteln!(&mut serial, "External prescaler: /{}", prescaler.ratio())
+                        .unwrap();
+                }
+                Command::PulsesPerRev(new_tachometer) => {
+                    tachometer = new_tachometer;
+                    tachometer.store(&eeprom);
+                    ufmt::uwriteln!(
+                        &mut serial,
+                        "Tachometer: {} pulses/rev",
+                        tachometer.pulses_per_rev()
+                    )
+                    .unwrap();
+                }
+                Command::KFactor(k_factor) => {
+                    flow.set_k_factor(k_factor);
+                    flow.store(&eeprom);
+                    ufmt::uwriteln!(&mut serial, "Flow K-factor: {} pulses/L", flow.k_factor())
+                        .unwrap();
+                }
+                Command::FlowReset => {
+                    flow.reset();
+                    ufmt::uwriteln!(&mut serial, "Flow volume reset").unwrap();
+                }
+                Command::A4(new_tuner) => {
+                    tuner = new_tuner;
+                    ufmt::uwriteln!(&mut serial, "Tuner: A4 = {} Hz", tuner.a4_hz()).unwrap();
+                }
+                Command::LcCalibrate(step) => {
+                    lc_meter.request(step);
+                }
+                Command::LcComponent(component) => {
+                    lc_meter.set_component(component);
+                    let name = match component {
+                        LcComponent::Capacitor => "capacitance",
+                        LcComponent::Inductor => "inductance",
+                    };
+                    ufmt::uwriteln!(&mut serial, "LC meter: measuring {}", name).unwrap();
+                }
+                Command::MainsNominal(nominal_hz) => {
+                    mains.set_nominal_hz(nominal_hz);
+                    ufmt::uwriteln!(&mut serial, "Mains nominal: {} Hz", nominal_hz).unwrap();
+                }
+                Command::MainsAlarm(alarm_mhz) => {
+                    mains.set_alarm_mhz(if alarm_mhz == 0 {
+                        None
+                    } else {
+                        Some(alarm_mhz)
+                    });
+                    ufmt::uwriteln!(&mut serial, "Mains alarm at {} mHz", alarm_mhz).unwrap();
+                }
+                Command::MainsReset => {
+                    mains.reset();
+                    ufmt::uwriteln!(&mut serial, "Mains session reset").unwrap();
+                }
+                Command::Nominal(nominal_hz) => {
+                    relative.set_nominal_hz(nominal_hz);
+                    let (hz_str, hz_unit) =
+                        format_utils::format_hz(nominal_hz, STATS_SIGNIFICANT_DIGITS);
+                    ufmt::uwriteln!(&mut serial, "Nominal: {} {}", hz_str.as_str(), hz_unit)
+                        .unwrap();
+                }
+                Command::NominalCapture => {
+                    relative.capture_next();
+                }
+                Command::LimitLow(low_hz) => {
+                    limits.set_low_hz(low_hz);
+                    match low_hz {
+                        Some(_) => ufmt::uwriteln!(&mut serial, "Low limit set").unwrap(),
+                        None => ufmt::uwriteln!(&mut serial, "Low limit disabled").unwrap(),
+                    }
+                }
+                Command::LimitHigh(high_hz) => {
+                    limits.set_high_hz(high_hz);
+                    match high_hz {
+                        Some(_) => ufmt::uwriteln!(&mut serial, "High limit set").unwrap(),
+                        None => ufmt::uwriteln!(&mut serial, "High limit disabled").unwrap(),
+                    }
+                }
+                Command::LimitHysteresis(hysteresis_hz) => {
+                    limits.set_hysteresis_hz(hysteresis_hz);
+                    ufmt::uwriteln!(&mut serial, "Limit hysteresis set").unwrap();
+                }
+                Command::Buzzer(enabled) => {
+                    buzzer_enabled = enabled;
+                    let state = if enabled { "on" } else { "off" };
+                    ufmt::uwriteln!(&mut serial, "Buzzer {}", state).unwrap();
+                }
+                Command::Hold(mode) => {
+                    new_hold = Some(mode);
+                }
+                Command::HoldReset => {
+                    hold.reset();
+                    ufmt::uwriteln!(&mut serial, "Hold peaks reset").unwrap();
+                }
+                Command::Edge(input) => {
+                    counter.set_input(input);
+                    ufmt::uwriteln!(&mut serial, "Counting {}", input_label(input)).unwrap();
+                }
+                Command::Total => {
+                    let total = format_utils::format_u64(totalizer.total());
+                    ufmt::uwriteln!(&mut serial, "Total: {}", total.as_str()).unwrap();
+                }
+                Command::TotalStart => {
+                    totalizer.start(counter.count());
+                    ufmt::uwriteln!(&mut serial, "Totalizer started").unwrap();
+                }
+                Command::TotalStop => {
+                    totalizer.stop(counter.count());
+                    ufmt::uwriteln!(&mut serial, "Totalizer stopped").unwrap();
+                }
+                Command::TotalReset => {
+                    totalizer.reset(counter.count());
+                    ufmt::uwriteln!(&mut serial, "Totalizer reset").unwrap();
+                }
+                Command::Preset(counts) => {
+                    totalizer.set_preset(if counts == 0 {
+                        None
+                    } else {
+                        Some(counts.into())
+                    });
+                    ufmt::uwriteln!(&mut serial, "Preset set to {}", counts).unwrap();
+                }
+                Command::Invalid => {
+                    ufmt::uwriteln!(&mut serial, "Invalid command").unwrap();
+                }
+            }
+        }
+
+        let now = clock.micros();
+        if mode_button.pressed(now) {
+            new_mode = Some(display_mode.next());
+        }
+        if start_button.pressed(now) {
+            if totalizer.is_running() {
+                totalizer.stop(counter.count());
+            } else {
+                totalizer.start(counter.count());
+            }
+        }
+        if reset_button.pressed(now) {
+            totalizer.reset(counter.count());
+        }
+        if hold_button.pressed(now) {
+            new_hold = Some(hold.mode().next());
+        }
+
+        if let Some(mode) = new_hold {
+            hold.set_mode(mode);
+            ufmt::uwriteln!(&mut serial, "Hold: {}", mode.name()).unwrap();
+
+            if mode == HoldMode::Off {
+                // show a fresh reading at the end of the current gate
+                last_refresh_micros = now.wrapping_sub(DISPLAY_REFRESH_MICROS);
+            }
+        }
+
+        if let Some(mode) = new_mode {
+            display_mode = mode;
+            ufmt::uwriteln!(&mut serial, "Display mode: {}", display_mode.name()).unwrap();
+
+            // Pulse widths wait for a direct reading to show that the input is
+            // slow enough, the tachometer and the mains monitor start out
+            // reciprocal to resolve slow signals without waiting for the
+            // auto-range, but only if the last reading was slow enough for the
+            // capture ISR to keep up
+            let slow_input =
+                last_pin_hz.map_or(false, |hz| hz < FixedU64::<U8>::from(CROSSOVER_HZ));
+            let wanted_mode = match display_mode {
+                DisplayMode::Tachometer | DisplayMode::Mains
+                    if pps_seconds.is_none() && slow_input =>
+                {
+                    Some(CountMode::Reciprocal)
+                }
+                _ if counter.mode() == CountMode::PulseWidth => Some(CountMode::Direct),
+                _ => None,
+            };
+
+            if let Some(wanted_mode) = wanted_mode.filter(|mode| *mode != counter.mode()) {
+                counter.set_mode(wanted_mode);
+                last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+                totalizer.rebase(last.count);
+                allan.reset();
+            }
+        }
+
+        // Only direct counting counts the input edges, the other methods would
+        // starve the totalizer
+        if totalizer.is_running() && counter.mode() != CountMode::Direct {
+            counter.set_mode(CountMode::Direct);
+            last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+            totalizer.rebase(last.count);
+            allan.reset();
+        }
+
+        // The edges go to the totalizer on every pass rather than at the end of
+        // the gate, which could run well past the preset
+        if totalizer.update(counter.count()) {
+            ufmt::uwriteln!(&mut serial, "Preset reached").unwrap();
+        }
+
+        if totalizer.preset_reached() {
+            batch_output.set_high();
+        } else {
+            batch_output.set_low();
+        }
+
+        // A board that stopped oscillating fails as well
+        let failing =
+            limits.is_enabled() && (!signal.is_present() || limits.state() != LimitState::Pass);
+        if failing {
+            fail_output.set_high();
+        } else {
+            fail_output.set_low();
+        }
+        if failing && buzzer_enabled {
+            buzzer.set_high();
+        } else {
+            buzzer.set_low();
+        }
+
+        // Checked on every pass, an input too fast for the capture interrupt
+        // would not let the gate close
+        let captured_edges = match counter.mode() {
+            CountMode::Direct => 0,
+            CountMode::Reciprocal => counter
+                .edge_capture()
+                .edges
+                .wrapping_sub(last.capture.edges),
+            CountMode::PulseWidth => counter
+                .pulse_capture()
+                .delta_since(last.pulse_widths)
+                .periods
+                .wrapping_mul(2),
+        };
+        if autorange.check_capture_rate(captured_edges, clock.micros().wrapping_sub(last.micros)) {
+            ufmt::uwriteln!(&mut serial, "Input too fast, counting directly").unwrap();
+            last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+            allan.reset();
+            continue;
+        }
+
+        let pps_pulse = pps.last_pulse();
+        if pps_pulse.pulses != last_pps_pulses {
+            last_pps_pulses = pps_pulse.pulses;
+            last_pps_micros = clock.micros();
+        }
+
+        // A GPS that lost its fix or got unplugged would keep the gate open
+        // for good, so we go back to timing it with the board clock
+        if pps_seconds.is_some()
+            && clock.micros().wrapping_sub(last_pps_micros) > PPS_TIMEOUT_MICROS
+        {
+            pps_seconds = None;
+            pps.set_enabled(false);
+            last = Snapshot::take(&counter, &clock, &pps);
+            allan.reset();
+            ufmt::uwriteln!(&mut serial, "No PPS, back to the board clock gate").unwrap();
+            continue;
+        }
+
+        // Keep serving the console until the gate closes
+        let gate_closed = match pps_seconds {
+            // The first pulse only gives us the start of the first gate
+            Some(_) if last.pps.pulses == 0 => {
+                last.pps = pps_pulse;
+                false
+            }
+            Some(seconds) => pps_pulse.pulses.wrapping_sub(last.pps.pulses) >= seconds,
+            None => clock.micros().wrapping_sub(last.micros) >= gate.as_micros(),
+        };
+
+        if !gate_closed {
+            continue;
+        }
+
+        // Take the two snapshots back to back, so that the elapsed time
+        // accounts for everything the loop body did since the last reading.
+        let meas = Snapshot::take(&counter, &clock, &pps);
+        let pulse_delta = meas.pulse_widths.delta_since(last.pulse_widths);
+
+        // The resolution of a reading is one count in direct mode and one CPU
+        // cycle in reciprocal mode, so that is what sets its significant digits.
+        let (delta_counts, micros_elapsed, resolved_counts) = match counter.mode() {
+            CountMode::Direct => match pps_seconds {
+                Some(_) => {
+                    // Exactly one second per pulse, whatever the CPU clock does
+                    let pulses = meas.pps.pulses.wrapping_sub(last.pps.pulses);
+                    let delta_counts = meas.pps.count.delta_since(last.pps.count);
+
+                    (
+                        delta_counts,
+                        FixedU64::<U8>::from(pulses * 1_000_000),
+                        delta_counts,
+                    )
+                }
+                None => {
+                    let delta_counts = meas.count.delta_since(last.count);
+
+                    (
+                        delta_counts,
+                        FixedU64::<U8>::from(meas.micros.wrapping_sub(last.micros)),
+                        delta_counts,
+                    )
+                }
+            },
+            CountMode::Reciprocal => {
+                if meas.capture.edges == last.capture.edges {
+                    // No new edge, no periods to time
+                    (0, FixedU64::<U8>::ZERO, 0)
+                } else if last.capture.edges == 0 {
+                    // The first edge only gives us the start of the first period
+                    last.capture = meas.capture;
+                    continue;
+                } else {
+                    // N input periods over the CPU cycles they took
+                    let delta_cycles = meas.capture.timestamp.wrapping_sub(last.capture.timestamp);
+
+                    (
+                        meas.capture.edges.wrapping_sub(last.capture.edges).into(),
+                        FixedU64::<U8>::from(delta_cycles) / CPU_CYCLES_PER_MICRO,
+                        delta_cycles.into(),
+                    )
+                }
+            }
+            CountMode::PulseWidth => {
+                // Whole periods over the CPU cycles spent high and low
+                let delta_cycles = pulse_delta.high_cycles.wrapping_add(pulse_delta.low_cycles);
+
+                (
+                    pulse_delta.periods.into(),
+                    FixedU64::<U8>::from(delta_cycles) / CPU_CYCLES_PER_MICRO,
+                    delta_cycles.into(),
+                )
+            }
+        };
+
+        // Flow sensors are slow enough to spend most of their time in
+        // reciprocal mode, so the volume takes the edges from whichever
+        // method is counting them
+        let gate_edges: u64 = match counter.mode() {
+            CountMode::Direct => meas.count.delta_since(last.count),
+            CountMode::Reciprocal => meas.capture.edges.wrapping_sub(last.capture.edges).into(),
+            CountMode::PulseWidth => pulse_delta.periods.into(),
+        };
+        flow.add(gate_edges * prescaler.ratio() as u64);
+
+        let signal_seen = delta_counts >= MIN_SIGNAL_COUNTS;
+        let transition = signal.update(signal_seen, meas.micros);
+
+        match transition {
+            Some(SignalState::Lost) => {
+                last_pin_hz = None;
+                ufmt::uwriteln!(&mut serial, "No signal").unwrap()
+            }
+            Some(SignalState::Present) => ufmt::uwriteln!(&mut serial, "Signal detected").unwrap(),
+            None => {}
+        }
+
+        if !signal_seen {
+            if transition == Some(SignalState::Lost)
+                && pps_seconds.is_none()
+                && counter.mode() != CountMode::Direct
+            {
+                // Wait in direct mode, the signal could come back at any
+                // frequency and only direct counting copes with a fast one
+                counter.set_mode(CountMode::Direct);
+                last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+                allan.reset();
+            } else {
+                last = meas;
+            }
+
+            // Keep showing the last reading until the timeout expires, or for
+            // as long as it is held
+            if !signal.is_present()
+                && hold.mode() == HoldMode::Off
+                && meas.micros.wrapping_sub(last_refresh_micros) >= DISPLAY_REFRESH_MICROS
+            {
+                last_refresh_micros = meas.micros;
+                let label = match display_mode {
+                    DisplayMode::Period => ui::period_label(prescaler.ratio()),
+                    DisplayMode::Tachometer => ui::line(&["Speed:"]),
+                    _ => ui::frequency_label(prescaler.ratio()),
+                };
+                let (first_line, second_line) = match display_mode {
+                    // a total is still worth showing without edges coming in
+                    DisplayMode::Totalize => ui::totalizer_lines(&totalizer),
+                    DisplayMode::Flow => {
+                        ui::flow_lines("0", method_label(counter.mode()), flow.volume_litres())
+                    }
+                    _ => (
+                        ui::tagged_line(label.as_str(), method_label(counter.mode())),
+                        ui::line(&[" No signal"]),
+                    ),
+                };
+                display
+                    .write_lines(first_line, second_line)
+                    .expect("Failed to write to display");
+            }
+
+            continue;
+        }
+        // The PPS gate does not depend on the board clock, so it needs no calibration
+        let delta_clock_cycles = match pps_seconds {
+            Some(_) => FixedU64::<U8>::from_num(delta_counts),
+            None => calibration.apply(FixedU64::<U8>::from_num(delta_counts)),
+        };
+
+        let method = match pps_seconds {
+            Some(_) => "PPS",
+            None => method_label(counter.mode()),
+        };
+        // The auto-range cares about the rate at the pin, everything else
+        // about the frequency in front of the prescaler
+        let pin_hz = frequency_hz(delta_clock_cycles, micros_elapsed);
+        last_pin_hz = Some(pin_hz);
+        let input_counts = prescaler.apply(delta_clock_cycles);
+
+        let freq_hz = frequency_hz(input_counts, micros_elapsed);
+        let unit = unit_selector.select(freq_hz);
+        let freq = unit.scale_counts(input_counts, micros_elapsed);
+        let f_unit = unit.symbol();
+
+        stats.add(freq_hz);
+        let gate_micros = match pps_seconds {
+            Some(seconds) => seconds * 1_000_000,
+            None => gate.as_micros(),
+        };
+        allan.add(freq_hz, micros_elapsed, gate_micros);
+        hold.update(Reading {
+            hz: freq_hz,
+            significant_digits: format_utils::count_digits(resolved_counts),
+        });
+
+        // Only while monitoring, whatever else is on the input is not the grid
+        if display_mode == DisplayMode::Mains {
+            match mains.add(input_counts, micros_elapsed) {
+                Some(true) => {
+                    let deviation = format_utils::format_signed(mains.deviation_mhz().unwrap_or(0));
+                    ufmt::uwriteln!(&mut serial, "Mains alarm: {} mHz", deviation.as_str()).unwrap()
+                }
+                Some(false) => ufmt::uwriteln!(&mut serial, "Mains back in band").unwrap(),
+                None => {}
+            }
+        }
+
+        match limits.update(freq_hz) {
+            Some(LimitState::Pass) => ufmt::uwriteln!(&mut serial, "Limits: pass").unwrap(),
+            Some(LimitState::Under) => ufmt::uwriteln!(&mut serial, "Limits: under").unwrap(),
+            Some(LimitState::Over) => ufmt::uwriteln!(&mut serial, "Limits: over").unwrap(),
+            None => {}
+        }
+
+        if let Some(nominal_hz) = relative.update(freq_hz) {
+            let (hz_str, hz_unit) = format_utils::format_hz(nominal_hz, STATS_SIGNIFICANT_DIGITS);
+            ufmt::uwriteln!(
+                &mut serial,
+                "Nominal captured: {} {}",
+                hz_str.as_str(),
+                hz_unit
+            )
             .unwrap();
+        }
 
-        while second_line.len() < second_line.capacity() {
-            second_line
-                .push(' ')
-                .or_else(|_| {
-                    ufmt::uwriteln!(&mut serial, "Failed to fill second line");
-                    Ok::<(), ()>(())
-                })
-                .unwrap();
+        match lc_meter.update(freq_hz) {
+            Some(Ok(LcStep::Zero)) => ufmt::uwriteln!(&mut serial, "LC meter zeroed").unwrap(),
+            Some(Ok(LcStep::Reference(_))) => {
+                ufmt::uwriteln!(&mut serial, "LC meter calibrated").unwrap()
+            }
+            Some(Err(_)) => ufmt::uwriteln!(
+                &mut serial,
+                "LC calibration failed, zero first and check the reference"
+            )
+            .unwrap(),
+            None => {}
         }
 
-        display
-            .write_line(second_line)
-            .expect("Failed to write to display");
+        let restarted = if display_mode == DisplayMode::Duty {
+            let wanted_mode = match counter.mode() {
+                CountMode::PulseWidth if pin_hz > FixedU64::<U8>::from(DUTY_MAX_HZ) => {
+                    Some(CountMode::Direct)
+                }
+                CountMode::Direct | CountMode::Reciprocal
+                    if pps_seconds.is_none()
+                        && !totalizer.is_running()
+                        && pin_hz < FixedU64::<U8>::from(DUTY_RESUME_HZ) =>
+                {
+                    Some(CountMode::PulseWidth)
+                }
+                _ => None,
+            };
+
+            match wanted_mode {
+                Some(mode) => {
+                    if mode == CountMode::Direct {
+                        ufmt::uwriteln!(&mut serial, "Too fast for the duty cycle").unwrap();
+                    }
+                    counter.set_mode(mode);
+                    true
+                }
+                None => false,
+            }
+        } else {
+            pps_seconds.is_none() && !totalizer.is_running() && autorange.update(pin_hz)
+        };
+
+        if restarted {
+            // The counter has been restarted, take fresh snapshots
+            last = Snapshot::restart(&counter, &clock, &pps, pps_seconds.is_some());
+            allan.reset();
+        } else {
+            last = meas;
+        }
+
+        if meas.micros.wrapping_sub(last_refresh_micros) < DISPLAY_REFRESH_MICROS {
+            continue;
+        }
+        last_refresh_micros = meas.micros;
+        refresh_count = refresh_count.wrapping_add(1);
 
-        last_clock_cycles_meas = clock_cycles_meas;
+        // The frequency layout shows the held readings, any other mode is
+        // just not refreshed while on hold
+        if hold.mode() == HoldMode::Hold && display_mode != DisplayMode::Frequency {
+            continue;
+        }
 
-        arduino_hal::delay_ms(delay_in_ms);
+        let (first_line, second_line) = match display_mode {
+            DisplayMode::Frequency if hold.mode() != HoldMode::Off => {
+                let held = match hold.reading() {
+                    Some(reading) => {
+                        let (hz_str, hz_unit) =
+                            format_utils::format_hz(reading.hz, reading.significant_digits);
+                        ui::line(&[" ", hz_str.as_str(), " ", hz_unit])
+                    }
+                    None => ui::line(&[" ---"]),
+                };
+
+                (
+                    ui::tagged_line(
+                        ui::frequency_label(prescaler.ratio()).as_str(),
+                        hold.mode().tag(),
+                    ),
+                    held,
+                )
+            }
+            DisplayMode::Frequency => {
+                let f_str =
+                    format_utils::format_scaled(freq, format_utils::count_digits(resolved_counts)); // ~2 ms
+
+                let reading = ui::line(&[" ", f_str.as_str(), " ", f_unit]);
+
+                (
+                    // show which method produced this reading in the top right corner
+                    ui::tagged_line(ui::frequency_label(prescaler.ratio()).as_str(), method),
+                    // and whether it is within the limits in the bottom right one
+                    match ui::limit_tag(&limits) {
+                        Some(tag) => ui::tagged_line(reading.as_str(), tag),
+                        None => reading,
+                    },
+                )
+            }
+            DisplayMode::Tachometer => {
+                let rpm = tachometer.rpm(input_counts, micros_elapsed);
+                let rpm_str =
+                    format_utils::format_wide(rpm, format_utils::count_digits(resolved_counts));
+
+                (
+                    ui::tagged_line("Speed:", method),
+                    ui::line(&[" ", rpm_str.as_str(), " RPM"]),
+                )
+            }
+            DisplayMode::Flow => {
+                let rate = flow.rate_lpm(input_counts, micros_elapsed);
+                let rate_str =
+                    format_utils::format_wide(rate, format_utils::count_digits(resolved_counts));
+
+                ui::flow_lines(rate_str.as_str(), method, flow.volume_litres())
+            }
+            DisplayMode::Tuner => ui::tuner_lines(tuner.note(freq_hz)),
+            DisplayMode::Lc => ui::lc_lines(&lc_meter, lc_meter.value(freq_hz), method),
+            DisplayMode::Mains => ui::mains_lines(&mains, method, refresh_count / 8 % 2 == 1),
+            DisplayMode::Relative => ui::relative_lines(
+                relative.deviation_hz(freq_hz),
+                relative.deviation_ppm(freq_hz),
+                method,
+            ),
+            DisplayMode::Statistics => {
+                // alternate between mean/deviation and min/max every two seconds
+                let ((first_label, first_value), (second_label, second_value)) =
+                    if refresh_count / 8 % 2 == 0 {
+                        (("avg ", stats.mean()), ("sd  ", stats.std_dev()))
+                    } else {
+                        (("min ", stats.min()), ("max ", stats.max()))
+                    };
+
+                let stats_line = |label: &str, value: Option<FixedU64<U8>>| match value {
+                    Some(value) => {
+                        let (v_str, v_unit) =
+                            format_utils::format_hz(value, STATS_SIGNIFICANT_DIGITS);
+                        ui::line(&[label, v_str.as_str(), " ", v_unit])
+                    }
+                    None => ui::line(&[label, "---"]),
+                };
+
+                (
+                    stats_line(first_label, first_value),
+                    stats_line(second_label, second_value),
+                )
+            }
+            DisplayMode::Period => {
+                // same measurement as the frequency, just the other way up
+                let t_unit = period_unit_selector.select(micros_elapsed / input_counts);
+                let period = t_unit.scale_period(input_counts, micros_elapsed);
+                let t_str = format_utils::format_scaled(
+                    period,
+                    format_utils::count_digits(resolved_counts),
+                );
+
+                (
+                    ui::tagged_line(ui::period_label(prescaler.ratio()).as_str(), method),
+                    ui::line(&[" ", t_str.as_str(), " ", t_unit.symbol()]),
+                )
+            }
+            DisplayMode::Duty => {
+                let f_str = format_utils::format_scaled(freq, DUTY_SIGNIFICANT_DIGITS);
+
+                ui::duty_lines(f_str.as_str(), f_unit, pulse_delta, CPU_CYCLES_PER_MICRO)
+            }
+            DisplayMode::Totalize => ui::totalizer_lines(&totalizer),
+        };
+
+        // This takes approximately ~100 ms on a 328p
+        display
+            .write_lines(first_line, second_line)
+            .expect("Failed to write to display");
     }
 }
@@ -0,0 +1,122 @@
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::units::Scaled;
+
+/// Largest alarm threshold we accept, in mHz.
+pub const MAX_ALARM_MHZ: u32 = 10_000;
+
+/// Grid frequency monitor, reporting the deviation from the nominal in mHz.
+///
+/// A single gate only holds a handful of mains cycles, so the readings are
+/// accumulated until they span `CYCLES` periods and the frequency is taken
+/// over all of them, in whole mHz. With the reciprocal method the reading is
+/// good to well under that, the limit is the calibration of the board clock.
+pub struct MainsMonitor {
+    nominal_hz: u32,
+    alarm_mhz: Option<u32>,
+    counts: FixedU64<U8>,
+    micros: FixedU64<U8>,
+    mhz: Option<u64>,
+    min_mhz: Option<i32>,
+    max_mhz: Option<i32>,
+    alarm: bool,
+}
+
+impl MainsMonitor {
+    /// Periods per reading, one second of a 50 Hz grid.
+    const CYCLES: u32 = 50;
+
+    pub const fn new(nominal_hz: u32) -> Self {
+        Self {
+            nominal_hz,
+            alarm_mhz: None,
+            counts: FixedU64::<U8>::ZERO,
+            micros: FixedU64::<U8>::ZERO,
+            mhz: None,
+            min_mhz: None,
+            max_mhz: None,
+            alarm: false,
+        }
+    }
+
+    /// Switches between 50 and 60 Hz grids, which starts a new session.
+    pub fn set_nominal_hz(&mut self, nominal_hz: u32) {
+        self.nominal_hz = nominal_hz;
+        self.reset();
+    }
+
+    /// Alarm when the deviation is more than `alarm_mhz` either way, `None`
+    /// to disable it.
+    pub fn set_alarm_mhz(&mut self, alarm_mhz: Option<u32>) {
+        self.alarm_mhz = alarm_mhz;
+    }
+
+    /// Starts a new session, forgetting the min/max so far.
+    pub fn reset(&mut self) {
+        *self = Self {
+            alarm_mhz: self.alarm_mhz,
+            ..Self::new(self.nominal_hz)
+        };
+    }
+
+    /// The last reading in Hz, with its three decimals.
+    pub fn hz(&self) -> Option<Scaled> {
+        self.mhz.map(|mhz| Scaled {
+            value: mhz,
+            decimals: 3,
+        })
+    }
+
+    pub fn deviation_mhz(&self) -> Option<i32> {
+        self.mhz.map(|mhz| self.to_deviation_mhz(mhz))
+    }
+
+    pub fn min_mhz(&self) -> Option<i32> {
+        self.min_mhz
+    }
+
+    pub fn max_mhz(&self) -> Option<i32> {
+        self.max_mhz
+    }
+
+    pub fn is_alarm(&self) -> bool {
+        self.alarm
+    }
+
+    /// Adds the `counts` periods of a gate, lasting `interval_micros`.
+    /// Returns the new alarm state when it changes.
+    pub fn add(&mut self, counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> Option<bool> {
+        self.counts += counts;
+        self.micros += interval_micros;
+
+        if self.counts < Self::CYCLES {
+            return None;
+        }
+
+        // both have 8 fractional bits, so their ratio is that of the raw bits
+        let (counts, micros) = (self.counts.to_bits(), self.micros.to_bits());
+        let mhz = (counts * 1_000_000_000 + micros / 2) / micros;
+        self.counts = FixedU64::<U8>::ZERO;
+        self.micros = FixedU64::<U8>::ZERO;
+        self.mhz = Some(mhz);
+
+        let deviation = self.to_deviation_mhz(mhz);
+        self.min_mhz = Some(self.min_mhz.map_or(deviation, |min| min.min(deviation)));
+        self.max_mhz = Some(self.max_mhz.map_or(deviation, |max| max.max(deviation)));
+
+        let alarm = self
+            .alarm_mhz
+            .map_or(false, |alarm_mhz| deviation.unsigned_abs() > alarm_mhz);
+
+        if alarm == self.alarm {
+            return None;
+        }
+
+        self.alarm = alarm;
+        Some(alarm)
+    }
+
+    fn to_deviation_mhz(&self, mhz: u64) -> i32 {
+        (mhz as i64 - self.nominal_hz as i64 * 1_000) as i32
+    }
+}
@@ -0,0 +1,70 @@
+// Works for ATMega328p
+//
+// A GPS 1PPS signal on INT0 (D2) defines the gate edges: the counter is
+// snapshotted in the interrupt handler, so the gate is as accurate as the
+// PPS and does not depend on the CPU clock at all.
+
+use arduino_hal::pac::EXINT;
+use avr_device::interrupt::Mutex;
+use core::cell::Cell;
+
+use crate::tcounter::{self, Count};
+
+static LAST_PULSE: Mutex<Cell<PpsPulse>> = Mutex::new(Cell::new(PpsPulse::ZERO));
+
+/// Number of PPS pulses seen so far and the counter value at the last one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PpsPulse {
+    pub pulses: u32,
+    pub count: Count,
+}
+
+impl PpsPulse {
+    const ZERO: Self = Self {
+        pulses: 0,
+        count: Count::ZERO,
+    };
+}
+
+pub struct PpsGate {
+    /// The external interrupt registers, gives this instance unique control over them.
+    exint: EXINT,
+}
+
+impl PpsGate {
+    /// Sets up INT0 to trigger on the rising edge, the interrupt stays
+    /// disabled until `set_enabled(true)` is called.
+    pub fn new(exint: EXINT) -> Self {
+        exint.eicra.modify(|_, w| w.isc0().bits(0x03));
+
+        Self { exint }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        avr_device::interrupt::free(|cs| {
+            LAST_PULSE.borrow(cs).set(PpsPulse::ZERO);
+
+            // clear any edge seen while we were not listening
+            self.exint.eifr.write(|w| w.intf0().set_bit());
+            self.exint.eimsk.modify(|_, w| w.int0().bit(enabled));
+        });
+    }
+
+    pub fn last_pulse(&self) -> PpsPulse {
+        avr_device::interrupt::free(|cs| LAST_PULSE.borrow(cs).get())
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn INT0() {
+    let count = tcounter::count_from_isr();
+
+    avr_device::interrupt::free(|cs| {
+        let pulse_cell = LAST_PULSE.borrow(cs);
+        let pulse = pulse_cell.get();
+        pulse_cell.set(PpsPulse {
+            pulses: pulse.pulses.wrapping_add(1),
+            count,
+        });
+    });
+}
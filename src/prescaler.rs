@@ -0,0 +1,53 @@
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::eeprom::Eeprom;
+
+const RECORD_ADDRESS: u16 = 16;
+const RECORD_VERSION: u8 = 1;
+
+/// Largest division ratio we accept.
+pub const MAX_RATIO: u16 = 1_000;
+
+/// Division ratio of an external prescaler in front of the counter input.
+///
+/// The T1 input tops out around 6 MHz, for RF work a divide-by-N chip goes
+/// in front of D5 (and D8) and the readings are multiplied back by N.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExternalPrescaler {
+    ratio: u16,
+}
+
+impl ExternalPrescaler {
+    pub const NONE: Self = Self { ratio: 1 };
+
+    pub const fn from_ratio(ratio: u16) -> Option<Self> {
+        match ratio {
+            1..=MAX_RATIO => Some(Self { ratio }),
+            _ => None,
+        }
+    }
+
+    pub const fn ratio(self) -> u16 {
+        self.ratio
+    }
+
+    /// Loads the prescaler record, if there is a valid one.
+    pub fn load(eeprom: &Eeprom) -> Option<Self> {
+        let mut payload = [0_u8; 2];
+
+        if eeprom.read_record(RECORD_ADDRESS, RECORD_VERSION, &mut payload) {
+            Self::from_ratio(u16::from_le_bytes(payload))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(self, eeprom: &Eeprom) {
+        eeprom.write_record(RECORD_ADDRESS, RECORD_VERSION, &self.ratio.to_le_bytes());
+    }
+
+    /// Converts the counts seen on the pin to the counts at the prescaler input.
+    pub fn apply(self, counts: FixedU64<U8>) -> FixedU64<U8> {
+        counts * self.ratio as u64
+    }
+}
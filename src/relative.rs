@@ -0,0 +1,57 @@
+use fixed::{types::extra::U8, FixedI64, FixedU64};
+
+/// Deviation of the readings from a nominal frequency, for trimming
+/// oscillators to a target.
+pub struct Relative {
+    nominal_hz: Option<FixedU64<U8>>,
+    capture: bool,
+}
+
+impl Default for Relative {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Relative {
+    pub const fn new() -> Self {
+        Self {
+            nominal_hz: None,
+            capture: false,
+        }
+    }
+
+    pub fn set_nominal_hz(&mut self, nominal_hz: FixedU64<U8>) {
+        self.nominal_hz = Some(nominal_hz);
+        self.capture = false;
+    }
+
+    /// Takes the next reading as the nominal.
+    pub fn capture_next(&mut self) {
+        self.capture = true;
+    }
+
+    /// Feeds a reading, returns it if it was captured as the nominal.
+    pub fn update(&mut self, hz: FixedU64<U8>) -> Option<FixedU64<U8>> {
+        if !self.capture || hz == 0 {
+            return None;
+        }
+
+        self.set_nominal_hz(hz);
+        Some(hz)
+    }
+
+    /// How far `hz` is from the nominal, in Hz.
+    pub fn deviation_hz(&self, hz: FixedU64<U8>) -> Option<FixedI64<U8>> {
+        let nominal_hz = self.nominal_hz?;
+
+        Some(FixedI64::<U8>::from_num(hz) - FixedI64::<U8>::from_num(nominal_hz))
+    }
+
+    /// How far `hz` is from the nominal, in parts per million of it.
+    pub fn deviation_ppm(&self, hz: FixedU64<U8>) -> Option<FixedI64<U8>> {
+        let nominal_hz = FixedI64::<U8>::from_num(self.nominal_hz?);
+
+        Some(self.deviation_hz(hz)? * 1_000_000 / nominal_hz)
+    }
+}
@@ -0,0 +1,59 @@
+/// Whether there is a signal at the input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SignalState {
+    Present,
+    Lost,
+}
+
+/// Declares the signal lost when no reading has seen it for a while.
+///
+/// A single empty gate is not enough, a slow signal can easily have no edge
+/// in a short gate, so the timeout has to be longer than the period of the
+/// slowest signal we want to measure.
+pub struct SignalMonitor {
+    timeout_micros: u32,
+    last_seen_micros: u32,
+    state: SignalState,
+}
+
+impl SignalMonitor {
+    /// Starts with the signal lost, until the first reading sees it.
+    pub fn new(timeout_ms: u32, now_micros: u32) -> Self {
+        Self {
+            timeout_micros: timeout_ms * 1_000,
+            last_seen_micros: now_micros,
+            state: SignalState::Lost,
+        }
+    }
+
+    pub fn timeout_ms(&self) -> u32 {
+        self.timeout_micros / 1_000
+    }
+
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_micros = timeout_ms * 1_000;
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.state == SignalState::Present
+    }
+
+    /// Feeds the outcome of a reading, returns the new state if it changed.
+    pub fn update(&mut self, seen: bool, now_micros: u32) -> Option<SignalState> {
+        let state = if seen {
+            self.last_seen_micros = now_micros;
+            SignalState::Present
+        } else if now_micros.wrapping_sub(self.last_seen_micros) >= self.timeout_micros {
+            SignalState::Lost
+        } else {
+            self.state
+        };
+
+        if state == self.state {
+            return None;
+        }
+
+        self.state = state;
+        Some(state)
+    }
+}
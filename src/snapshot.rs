@@ -0,0 +1,41 @@
+use crate::pps::{PpsGate, PpsPulse};
+use crate::tcounter::{Count, EdgeCapture, PulseCapture, TCounter};
+use crate::timerclock::TClock;
+
+/// Everything a reading is the difference of, taken back to back.
+///
+/// A reading is always the difference of two snapshots, so whenever the
+/// counter is restarted the previous one has to be replaced with `restart`.
+#[derive(Debug, Copy, Clone)]
+pub struct Snapshot {
+    pub count: Count,
+    pub micros: u32,
+    pub capture: EdgeCapture,
+    pub pulse_widths: PulseCapture,
+    pub pps: PpsPulse,
+}
+
+impl Snapshot {
+    pub fn take(counter: &TCounter, clock: &TClock, pps: &PpsGate) -> Self {
+        Self {
+            count: counter.count(),
+            micros: clock.micros(),
+            capture: counter.edge_capture(),
+            pulse_widths: counter.pulse_capture(),
+            pps: pps.last_pulse(),
+        }
+    }
+
+    /// Takes the snapshot to measure from once the counter has been restarted.
+    ///
+    /// The pulse the PPS gate captured holds a count from before the restart,
+    /// so when the gate is in use it is re-armed to start over from the next
+    /// pulse.
+    pub fn restart(counter: &TCounter, clock: &TClock, pps: &PpsGate, pps_enabled: bool) -> Self {
+        if pps_enabled {
+            pps.set_enabled(true);
+        }
+
+        Self::take(counter, clock, pps)
+    }
+}
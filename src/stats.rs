@@ -0,0 +1,132 @@
+use fixed::{types::extra::U8, FixedU64};
+use ufmt_float::uFmt_f32;
+
+/// Integer square root, rounded down.
+pub(crate) fn isqrt(value: u64) -> u64 {
+    let mut result: u64 = 0;
+    let mut bit: u64 = 1 << 62;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    let mut rest = value;
+    while bit != 0 {
+        if rest >= result + bit {
+            rest -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    result
+}
+
+/// Running statistics over the readings, without floating point.
+///
+/// The sums are kept as deviations from the first sample, in units of the
+/// fixed point LSB (1/256 Hz), so they stay small for the stable signals we
+/// usually look at. The sum of squares saturates instead of wrapping.
+#[derive(Debug, Copy, Clone)]
+pub struct Statistics {
+    count: u32,
+    first: FixedU64<U8>,
+    min: FixedU64<U8>,
+    max: FixedU64<U8>,
+    sum: i64,
+    sum_sq: u64,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Statistics {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            first: FixedU64::<U8>::ZERO,
+            min: FixedU64::<U8>::MAX,
+            max: FixedU64::<U8>::ZERO,
+            sum: 0,
+            sum_sq: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn add(&mut self, value: FixedU64<U8>) {
+        if self.count == 0 {
+            self.first = value;
+        }
+
+        let deviation = value.to_bits() as i64 - self.first.to_bits() as i64;
+        let deviation_abs = deviation.unsigned_abs();
+
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += deviation;
+        self.sum_sq = self
+            .sum_sq
+            .saturating_add(deviation_abs.saturating_mul(deviation_abs));
+    }
+
+    pub fn min(&self) -> Option<FixedU64<U8>> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<FixedU64<U8>> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<FixedU64<U8>> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let mean_deviation = self.sum / self.count as i64;
+        let mean = self.first.to_bits() as i64 + mean_deviation;
+
+        Some(FixedU64::<U8>::from_bits(mean as u64))
+    }
+
+    /// Sample standard deviation, needs at least two samples.
+    pub fn std_dev(&self) -> Option<FixedU64<U8>> {
+        if self.count < 2 {
+            return None;
+        }
+
+        // sum_sq - sum^2 / n, without squaring the sum
+        let mean_deviation = self.sum / self.count as i64;
+        let squares = self
+            .sum_sq
+            .saturating_sub(mean_deviation.saturating_mul(self.sum).unsigned_abs());
+        let variance = squares / (self.count - 1) as u64;
+
+        Some(FixedU64::<U8>::from_bits(isqrt(variance)))
+    }
+
+    /// Writes all the statistics on one line, values in Hz.
+    pub fn report<W: ufmt::uWrite>(&self, w: &mut W) -> Result<(), W::Error> {
+        let to_disp = |value: Option<FixedU64<U8>>| {
+            uFmt_f32::Three(value.unwrap_or(FixedU64::<U8>::ZERO).to_num::<f32>())
+        };
+
+        ufmt::uwriteln!(
+            w,
+            "n={} mean={} min={} max={} sd={} Hz",
+            self.count,
+            to_disp(self.mean()),
+            to_disp(self.min()),
+            to_disp(self.max()),
+            to_disp(self.std_dev()),
+        )
+    }
+}
@@ -0,0 +1,61 @@
+use fixed::{types::extra::U8, FixedU64};
+
+use crate::eeprom::Eeprom;
+
+const RECORD_ADDRESS: u16 = 24;
+const RECORD_VERSION: u8 = 1;
+
+/// Most pulses per revolution we accept, e.g. a 256 slot encoder disc.
+pub const MAX_PULSES_PER_REV: u16 = 1_000;
+
+/// Converts the sensor frequency to revolutions per minute.
+///
+/// An optical sensor on a single reflective strip gives one pulse per
+/// revolution, a hall sensor gives one per magnet pole pair and an encoder
+/// one per slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tachometer {
+    pulses_per_rev: u16,
+}
+
+impl Tachometer {
+    pub const ONE_PULSE: Self = Self { pulses_per_rev: 1 };
+
+    pub const fn from_pulses_per_rev(pulses_per_rev: u16) -> Option<Self> {
+        match pulses_per_rev {
+            1..=MAX_PULSES_PER_REV => Some(Self { pulses_per_rev }),
+            _ => None,
+        }
+    }
+
+    pub const fn pulses_per_rev(self) -> u16 {
+        self.pulses_per_rev
+    }
+
+    /// Loads the tachometer record, if there is a valid one.
+    pub fn load(eeprom: &Eeprom) -> Option<Self> {
+        let mut payload = [0_u8; 2];
+
+        if eeprom.read_record(RECORD_ADDRESS, RECORD_VERSION, &mut payload) {
+            Self::from_pulses_per_rev(u16::from_le_bytes(payload))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(self, eeprom: &Eeprom) {
+        eeprom.write_record(
+            RECORD_ADDRESS,
+            RECORD_VERSION,
+            &self.pulses_per_rev.to_le_bytes(),
+        );
+    }
+
+    /// Revolutions per minute of `counts` pulses in `interval_micros`.
+    ///
+    /// Like `Unit::scale_counts` the counts are scaled up before the division,
+    /// so the slow readings keep their decimals.
+    pub fn rpm(self, counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> FixedU64<U8> {
+        counts * 60_000_000 / interval_micros / self.pulses_per_rev as u64
+    }
+}
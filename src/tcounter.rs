@@ -6,57 +6,263 @@ use core::cell::Cell;
 
 static OVERFLOW_COUNTER: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
 
+/// Last edge seen by the input capture unit, only updated in reciprocal mode.
+static EDGE_CAPTURE: Mutex<Cell<EdgeCapture>> = Mutex::new(Cell::new(EdgeCapture::ZERO));
+
+/// Accumulated high and low times on ICP1, only updated in pulse width mode.
+static PULSE_CAPTURE: Mutex<Cell<PulseCapture>> = Mutex::new(Cell::new(PulseCapture::ZERO));
+/// High time of the period in progress, waiting for its low time.
+static PENDING_HIGH: Mutex<Cell<Option<u32>>> = Mutex::new(Cell::new(None));
+/// Tells the capture interrupt to alternate between the two edges.
+static TOGGLE_EDGE: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// How TC1 is used to measure the input signal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CountMode {
+    /// Count the input edges on T1 (D5) over a gate time.
+    Direct,
+    /// Run the timer on the CPU clock and timestamp the input edges on ICP1 (D8).
+    Reciprocal,
+    /// Like `Reciprocal`, but capturing both edges to time the high and low
+    /// parts of every period separately.
+    PulseWidth,
+}
+
+/// Which signal the counter counts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CounterInput {
+    /// Rising edges of the input
+    RisingEdge,
+    /// Falling edges of the input
+    FallingEdge,
+    /// The CPU clock, to test the counter without any signal
+    CpuClock,
+}
+
+/// Number of edges seen on ICP1 and the timestamp of the last one, in CPU cycles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EdgeCapture {
+    pub edges: u32,
+    pub timestamp: u32,
+}
+
+impl EdgeCapture {
+    const ZERO: Self = Self {
+        edges: 0,
+        timestamp: 0,
+    };
+}
+
+/// Snapshot of the extended counter: overflows * 65536 + TCNT1.
+///
+/// The overflow counter is 32 bits wide, so the count wraps around at 2^48.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Count(u64);
+
+impl Count {
+    pub const ZERO: Self = Self(0);
+    const MASK: u64 = (1 << 48) - 1;
+
+    /// Number of edges counted between `previous` and this snapshot, correct
+    /// across the wrap around.
+    pub const fn delta_since(self, previous: Count) -> u64 {
+        self.0.wrapping_sub(previous.0) & Self::MASK
+    }
+}
+
+/// High and low times of the complete periods seen on ICP1, in CPU cycles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PulseCapture {
+    pub periods: u32,
+    pub high_cycles: u32,
+    pub low_cycles: u32,
+}
+
+impl PulseCapture {
+    const ZERO: Self = Self {
+        periods: 0,
+        high_cycles: 0,
+        low_cycles: 0,
+    };
+
+    /// What was accumulated between `previous` and this snapshot.
+    pub const fn delta_since(self, previous: PulseCapture) -> PulseCapture {
+        PulseCapture {
+            periods: self.periods.wrapping_sub(previous.periods),
+            high_cycles: self.high_cycles.wrapping_sub(previous.high_cycles),
+            low_cycles: self.low_cycles.wrapping_sub(previous.low_cycles),
+        }
+    }
+}
+
 pub struct TCounter {
     /// The timer register, gives this instance unique control over it.
     tc1: TC1,
+    input: Cell<CounterInput>,
+    mode: Cell<CountMode>,
 }
 
 impl TCounter {
-    pub fn new(tc1: TC1, use_pin_d5: bool) -> TCounter {
+    pub fn new(tc1: TC1, input: CounterInput) -> TCounter {
+        let counter = Self {
+            tc1,
+            input: Cell::new(input),
+            mode: Cell::new(CountMode::Direct),
+        };
+        counter.set_mode(CountMode::Direct);
+
+        counter
+    }
+
+    pub fn mode(&self) -> CountMode {
+        self.mode.get()
+    }
+
+    /// Changes the counted signal on the fly.
+    ///
+    /// Unlike `set_mode` this does not restart the counter, the edges counted
+    /// so far are kept.
+    pub fn set_input(&self, input: CounterInput) {
+        self.input.set(input);
+
+        avr_device::interrupt::free(|_cs| match self.mode.get() {
+            CountMode::Direct => {
+                self.tc1.tccr1b.modify(|_, w| match input {
+                    CounterInput::RisingEdge => w.cs1().ext_rising(),
+                    CounterInput::FallingEdge => w.cs1().ext_falling(),
+                    CounterInput::CpuClock => w.cs1().direct(),
+                });
+            }
+            CountMode::Reciprocal => {
+                self.tc1
+                    .tccr1b
+                    .modify(|_, w| w.ices1().bit(input != CounterInput::FallingEdge));
+                // changing the edge can trigger a capture by itself
+                self.tc1.tifr1.write(|w| w.icf1().set_bit());
+            }
+            // both edges are captured anyway
+            CountMode::PulseWidth => {}
+        });
+    }
+
+    /// Reconfigures TC1 for the given measurement mode.
+    ///
+    /// The overflow counter and the edge capture are reset, so any snapshot
+    /// taken before this call must not be compared with the ones after it.
+    pub fn set_mode(&self, mode: CountMode) {
         avr_device::interrupt::free(|cs| {
+            // stop the timer while we reconfigure it
+            self.tc1.tccr1b.write(|w| w.cs1().no_clock());
+            self.tc1.timsk1.reset();
+            self.tc1.tcnt1.write(|w| unsafe { w.bits(0) });
+            // pending flags are cleared by writing a one to them
+            self.tc1
+                .tifr1
+                .write(|w| w.tov1().set_bit().icf1().set_bit());
+
             OVERFLOW_COUNTER.borrow(cs).set(0);
-        });
+            EDGE_CAPTURE.borrow(cs).set(EdgeCapture::ZERO);
+            PULSE_CAPTURE.borrow(cs).set(PulseCapture::ZERO);
+            PENDING_HIGH.borrow(cs).set(None);
+            TOGGLE_EDGE.borrow(cs).set(mode == CountMode::PulseWidth);
 
-        // set the timer/counter in normal mode
-        tc1.tccr1a.write(|w| w.wgm1().bits(0));
+            // set the timer/counter in normal mode
+            self.tc1.tccr1a.write(|w| w.wgm1().bits(0));
 
-        if use_pin_d5 {
-            // set clock source to external clock on rising edge
-            tc1.tccr1b.write(|w| w.cs1().ext_rising())
-        } else {
-            // set clock source to CPU clock
-            tc1.tccr1b.write(|w| w.cs1().direct())
-        }
+            match mode {
+                CountMode::Direct => {
+                    // set clock source to the external clock on the selected
+                    // edge, or to the CPU clock
+                    self.tc1.tccr1b.write(|w| match self.input.get() {
+                        CounterInput::RisingEdge => w.cs1().ext_rising(),
+                        CounterInput::FallingEdge => w.cs1().ext_falling(),
+                        CounterInput::CpuClock => w.cs1().direct(),
+                    });
+
+                    // enable counter overflow interrupt
+                    self.tc1.timsk1.write(|w| w.toie1().set_bit());
+                }
+                CountMode::Reciprocal => {
+                    // run on the CPU clock and capture the selected edge on
+                    // ICP1, with the noise canceler on
+                    let rising = self.input.get() != CounterInput::FallingEdge;
+                    self.tc1
+                        .tccr1b
+                        .write(|w| w.icnc1().set_bit().ices1().bit(rising).cs1().direct());
 
-        // enable counter overflow interrupt
-        tc1.timsk1.write(|w| w.toie1().set_bit());
+                    // enable counter overflow and input capture interrupts
+                    self.tc1
+                        .timsk1
+                        .write(|w| w.toie1().set_bit().icie1().set_bit());
+                }
+                CountMode::PulseWidth => {
+                    // start from a rising edge, the interrupt handler then
+                    // alternates between the two
+                    self.tc1
+                        .tccr1b
+                        .write(|w| w.icnc1().set_bit().ices1().set_bit().cs1().direct());
 
-        Self { tc1 }
+                    // enable counter overflow and input capture interrupts
+                    self.tc1
+                        .timsk1
+                        .write(|w| w.toie1().set_bit().icie1().set_bit());
+                }
+            }
+        });
+
+        self.mode.set(mode);
     }
 
-    pub fn clock_cycles(&self) -> u32 {
-        let (mut m, t, ov1) = avr_device::interrupt::free(|cs| {
-            let m: u32 = OVERFLOW_COUNTER.borrow(cs).get().into();
+    /// Returns the number of edges captured so far and when the last one happened.
+    ///
+    /// Only meaningful in `CountMode::Reciprocal`: the frequency is the
+    /// difference in edges between two snapshots divided by the difference in
+    /// timestamps, which is always an exact number of input periods.
+    pub fn edge_capture(&self) -> EdgeCapture {
+        avr_device::interrupt::free(|cs| EDGE_CAPTURE.borrow(cs).get())
+    }
 
-            let (t, ov1) = {
-                let t: u16 = self.tc1.tcnt1.read().bits();
-                let ov1: bool = self.tc1.tifr1.read().tov1().bit();
+    /// Returns the high and low times accumulated so far.
+    ///
+    /// Only meaningful in `CountMode::PulseWidth`, the differences between
+    /// two snapshots give the average pulse widths and the duty cycle, and
+    /// the frequency as periods over the sum of the two.
+    pub fn pulse_capture(&self) -> PulseCapture {
+        avr_device::interrupt::free(|cs| PULSE_CAPTURE.borrow(cs).get())
+    }
 
-                (t, ov1)
-            };
+    /// Returns the number of input edges counted so far, extended to 64 bits.
+    pub fn count(&self) -> Count {
+        read_count(&self.tc1)
+    }
+}
 
-            (m, t, ov1)
-        });
+/// Same as `TCounter::count`, for interrupt handlers that need to snapshot
+/// the counter and cannot get hold of the `TCounter` instance.
+pub(crate) fn count_from_isr() -> Count {
+    // SAFETY: we only read TC1 registers, the TCounter owning it cannot run
+    // while we are in the interrupt handler.
+    read_count(unsafe { &*TC1::ptr() })
+}
+
+fn read_count(tc1: &TC1) -> Count {
+    let (m, t) = avr_device::interrupt::free(|cs| {
+        let m: u32 = OVERFLOW_COUNTER.borrow(cs).get();
 
-        // Check whether a interrupt was pending when we read the counter value,
-        // which typically means it wrapped around, without the millis getting
-        // incremented, so we do it here manually:
-        if ov1 {
-            m += 1;
+        // Read the flag after the counter value: if it is set and the value
+        // is small, the timer wrapped around before we read it, but the
+        // overflow interrupt did not have the chance to run yet.
+        let t: u16 = tc1.tcnt1.read().bits();
+        let ov1: bool = tc1.tifr1.read().tov1().bit();
+
+        if ov1 && t < 0x8000 {
+            (m.wrapping_add(1), t)
+        } else {
+            (m, t)
         }
+    });
 
-        m * core::u16::MAX as u32 + t as u32
-    }
+    Count(((m as u64) << 16) | t as u64)
 }
 
 #[avr_device::interrupt(atmega328p)]
@@ -66,6 +272,66 @@ fn TIMER1_OVF() {
     avr_device::interrupt::free(|cs| {
         let counter_cell = OVERFLOW_COUNTER.borrow(cs);
         let counter = counter_cell.get();
-        counter_cell.set(counter + 1);
+        counter_cell.set(counter.wrapping_add(1));
+    });
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn TIMER1_CAPT() {
+    // SAFETY: we only read TC1 registers, the TCounter owning it cannot run
+    // while we are in the interrupt handler.
+    let tc1 = unsafe { &*TC1::ptr() };
+
+    avr_device::interrupt::free(|cs| {
+        let icr: u16 = tc1.icr1.read().bits();
+        let mut m: u32 = OVERFLOW_COUNTER.borrow(cs).get();
+
+        // The capture interrupt has priority over the overflow one, so the
+        // overflow might still be pending. It only belongs to this edge if
+        // the captured value comes from after the wrap around.
+        if tc1.tifr1.read().tov1().bit() && icr < 0x8000 {
+            m = m.wrapping_add(1);
+        }
+
+        let timestamp = m.wrapping_mul(0x1_0000).wrapping_add(icr as u32);
+
+        let capture_cell = EDGE_CAPTURE.borrow(cs);
+        let capture = capture_cell.get();
+        capture_cell.set(EdgeCapture {
+            edges: capture.edges.wrapping_add(1),
+            timestamp,
+        });
+
+        if !TOGGLE_EDGE.borrow(cs).get() {
+            return;
+        }
+
+        // Look for the other edge next, the flag has to be cleared after
+        // changing the edge
+        let rising = tc1.tccr1b.read().ices1().bit();
+        tc1.tccr1b.modify(|_, w| w.ices1().bit(!rising));
+        tc1.tifr1.write(|w| w.icf1().set_bit());
+
+        // The very first edge only starts the measurement
+        if capture.edges == 0 {
+            return;
+        }
+
+        let width = timestamp.wrapping_sub(capture.timestamp);
+        let pending_cell = PENDING_HIGH.borrow(cs);
+
+        if !rising {
+            // rising to falling edge, a high time
+            pending_cell.set(Some(width));
+        } else if let Some(high) = pending_cell.take() {
+            // falling to rising edge, a low time which completes a period
+            let pulse_cell = PULSE_CAPTURE.borrow(cs);
+            let pulse = pulse_cell.get();
+            pulse_cell.set(PulseCapture {
+                periods: pulse.periods.wrapping_add(1),
+                high_cycles: pulse.high_cycles.wrapping_add(high),
+                low_cycles: pulse.low_cycles.wrapping_add(width),
+            });
+        }
     });
 }
@@ -4,6 +4,8 @@
 // Original source:
 // https://github.com/Rahix/avr-hal/blob/e897783816437a677aa577ddfdaa34e9a1e86d96/examples/arduino-uno/src/bin/uno-millis.rs#L15-L71
 
+#![allow(dead_code)]
+
 use core::cell::Cell;
 use core::marker::PhantomData;
 
@@ -232,7 +234,8 @@ impl<ClockFreq: Clock> TimerClock<ClockFreq> {
         // Configure the timer for the above interval (in CTC mode)
         // and enable its interrupt.
         tc0.tccr0a.write(|w| w.wgm0().ctc());
-        tc0.ocr0a.write(|w| unsafe { w.bits(timer_cnt) });
+        // The counter goes through OCR0A + 1 values per period in CTC mode
+        tc0.ocr0a.write(|w| unsafe { w.bits(timer_cnt - 1) });
         tc0.tccr0b.write(|w| match prescaler {
             Prescaler::P1 => w.cs0().direct(),
             Prescaler::P8 => w.cs0().prescale_8(),
@@ -248,7 +251,7 @@ impl<ClockFreq: Clock> TimerClock<ClockFreq> {
         Ok(Self {
             _clock_frq: PhantomData,
             um_p_cnt,
-            max_cnt: timer_cnt,
+            max_cnt: timer_cnt - 1,
             tc0,
             res,
         })
@@ -267,8 +270,8 @@ impl<ClockFreq: Clock> TimerClock<ClockFreq> {
         // Get the current number of "millis" interrupts
         let m = avr_device::interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get());
 
-        // Calculate the proper millisecond value
-        m * self.res.as_ms()
+        // Calculate the proper millisecond value, wrapping like `micros`
+        m.wrapping_mul(self.res.as_ms())
     }
 
     /// Returns the number of microseconds since this clock was started
@@ -309,12 +312,13 @@ impl<ClockFreq: Clock> TimerClock<ClockFreq> {
         // which typically means it wrapped around, without the millis getting
         // incremented, so we do it here manually:
         if tifr && t < self.max_cnt {
-            m += 1;
+            m = m.wrapping_add(1);
         }
 
-        let millis = m * self.res.as_ms();
+        // Wraps after ~71.6 minutes, callers only look at differences
+        let millis = m.wrapping_mul(self.res.as_ms());
 
-        millis * 1000 + counter_micros
+        millis.wrapping_mul(1000).wrapping_add(counter_micros)
     }
 
     /// Returns the number of microseconds since this clock was started
@@ -332,7 +336,7 @@ fn TIMER0_COMPA() {
     avr_device::interrupt::free(|cs| {
         let counter_cell = MILLIS_COUNTER.borrow(cs);
         let counter = counter_cell.get();
-        counter_cell.set(counter + 1);
+        counter_cell.set(counter.wrapping_add(1));
     })
 }
 
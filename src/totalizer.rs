@@ -0,0 +1,102 @@
+use crate::tcounter::Count;
+
+/// Accumulates the edges counted in direct mode, for event and batch counting.
+///
+/// It shares the exact 64-bit count of `TCounter`, keeping the count it has
+/// added up to, so starting, stopping and resetting take effect on the edge
+/// and not at the end of the gate. That count goes stale every time the
+/// counter is restarted, so it has to be rebased then.
+pub struct Totalizer {
+    running: bool,
+    total: u64,
+    preset: Option<u64>,
+    base: Count,
+}
+
+impl Default for Totalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Totalizer {
+    pub const fn new() -> Self {
+        Self {
+            running: false,
+            total: 0,
+            preset: None,
+            base: Count::ZERO,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Counts left before the preset, if there is one.
+    pub fn remaining(&self) -> Option<u64> {
+        self.preset.map(|preset| preset.saturating_sub(self.total))
+    }
+
+    /// Whether the batch is complete.
+    pub fn preset_reached(&self) -> bool {
+        self.remaining() == Some(0)
+    }
+
+    /// Starts or resumes counting from `now`, unless the batch is already
+    /// complete.
+    pub fn start(&mut self, now: Count) {
+        if !self.running {
+            self.base = now;
+        }
+        self.running = !self.preset_reached();
+    }
+
+    /// Stops counting, with the edges up to `now` added.
+    pub fn stop(&mut self, now: Count) {
+        self.update(now);
+        self.running = false;
+    }
+
+    /// Clears the total, keeping the preset and whether we are counting.
+    pub fn reset(&mut self, now: Count) {
+        self.total = 0;
+        self.base = now;
+    }
+
+    /// Counts down from `preset` from now on, `None` to just count up.
+    pub fn set_preset(&mut self, preset: Option<u64>) {
+        self.preset = preset;
+
+        if self.preset_reached() {
+            self.running = false;
+        }
+    }
+
+    /// Carries on from `now` after the counter has been restarted.
+    pub fn rebase(&mut self, now: Count) {
+        self.base = now;
+    }
+
+    /// Adds the edges counted since the last update. Returns true when they
+    /// complete the batch, which also stops the count.
+    pub fn update(&mut self, now: Count) -> bool {
+        if !self.running {
+            return false;
+        }
+
+        self.total = self.total.saturating_add(now.delta_since(self.base));
+        self.base = now;
+
+        if self.preset_reached() {
+            self.running = false;
+            return true;
+        }
+
+        false
+    }
+}
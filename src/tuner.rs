@@ -0,0 +1,94 @@
+use fixed::{types::extra::U8, FixedU64};
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Reference pitches we accept for A4, covering baroque to modern orchestras.
+const MIN_A4_HZ: u16 = 400;
+const MAX_A4_HZ: u16 = 480;
+
+/// The equal-tempered note closest to a frequency.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub name: &'static str,
+    pub octave: u8,
+    /// How far off the note the frequency is, from -50 to +50
+    pub cents: i16,
+}
+
+/// Converts frequencies to notes, given the reference pitch of A4.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tuner {
+    a4_hz: u16,
+}
+
+impl Tuner {
+    pub const DEFAULT: Self = Self { a4_hz: 440 };
+
+    pub const fn from_a4_hz(a4_hz: u16) -> Option<Self> {
+        match a4_hz {
+            MIN_A4_HZ..=MAX_A4_HZ => Some(Self { a4_hz }),
+            _ => None,
+        }
+    }
+
+    pub const fn a4_hz(self) -> u16 {
+        self.a4_hz
+    }
+
+    /// The note closest to `hz`, if it is within octaves 0 to 9.
+    pub fn note(self, hz: FixedU64<U8>) -> Option<Note> {
+        if hz == 0 {
+            return None;
+        }
+
+        let a4 = FixedU64::<U8>::from(self.a4_hz);
+        let semitones = (log2_q16(hz.to_bits()) - log2_q16(a4.to_bits())) * 12;
+
+        // round to the nearest semitone, what is left over are the cents
+        let from_a4 = (semitones + (1 << 15)) >> 16;
+        let cents = ((semitones - (from_a4 << 16)) * 100 + (1 << 15)) >> 16;
+
+        // count from C0, nine semitones below A0
+        let from_c0 = from_a4 + 4 * 12 + 9;
+        let octave = from_c0.div_euclid(12);
+        if !(0..=9).contains(&octave) {
+            return None;
+        }
+
+        Some(Note {
+            name: NOTE_NAMES[from_c0.rem_euclid(12) as usize],
+            octave: octave as u8,
+            cents: cents as i16,
+        })
+    }
+}
+
+/// Base 2 logarithm of `x`, with 16 fractional bits.
+///
+/// There is no `log2` without std, so the integer part comes from the
+/// position of the top bit and the fraction from squaring the mantissa one
+/// bit at a time.
+fn log2_q16(x: u64) -> i64 {
+    let integer_part = 63 - x.leading_zeros() as i64;
+
+    // the mantissa in [1, 2), with 31 fractional bits
+    let mut mantissa = if integer_part >= 31 {
+        x >> (integer_part - 31)
+    } else {
+        x << (31 - integer_part)
+    };
+    let mut result = integer_part << 16;
+
+    for bit in (0..16).rev() {
+        mantissa = (mantissa * mantissa) >> 31;
+
+        if mantissa >= 2 << 31 {
+            mantissa >>= 1;
+            result |= 1 << bit;
+        }
+    }
+
+    result
+}
@@ -0,0 +1,385 @@
+use fixed::{types::extra::U8, FixedI64, FixedU64};
+use heapless::String;
+
+use crate::format_utils;
+use crate::lcmeter::{LcComponent, LcMeter};
+use crate::limits::{LimitState, Limits};
+use crate::mains::MainsMonitor;
+use crate::tcounter::PulseCapture;
+use crate::totalizer::Totalizer;
+use crate::tuner::Note;
+
+/// Cells of the tuner scale, an odd number so that 0 cents sits right in the
+/// middle of the central cell.
+const NEEDLE_CELLS: usize = 15;
+
+/// CGRAM slot of the needle in the leftmost column, the other four follow.
+const NEEDLE_SLOT: u8 = 1;
+/// CGRAM slot of the tick marking the middle of the scale.
+const CENTER_TICK_SLOT: u8 = 6;
+
+/// Custom characters of the tuner needle, as (CGRAM slot, rows).
+pub const CUSTOM_CHARS: [(u8, [u8; 8]); 6] = [
+    (NEEDLE_SLOT, [0b10000; 8]),
+    (NEEDLE_SLOT + 1, [0b01000; 8]),
+    (NEEDLE_SLOT + 2, [0b00100; 8]),
+    (NEEDLE_SLOT + 3, [0b00010; 8]),
+    (NEEDLE_SLOT + 4, [0b00001; 8]),
+    (CENTER_TICK_SLOT, [0, 0, 0, 0, 0, 0, 0b00100, 0b00100]),
+];
+
+/// What the display is showing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// The last reading
+    Frequency,
+    /// The statistics accumulated since the last reset
+    Statistics,
+    /// Frequency, duty cycle and pulse widths
+    Duty,
+    /// The last reading, as a period
+    Period,
+    /// The last reading, as revolutions per minute
+    Tachometer,
+    /// Flow rate and dispensed volume
+    Flow,
+    /// The closest note and how far off it we are
+    Tuner,
+    /// The component on the LC oscillator
+    Lc,
+    /// Grid frequency and its deviation from the nominal
+    Mains,
+    /// Deviation of the last reading from a nominal, in Hz and ppm
+    Relative,
+    /// The totalizer count
+    Totalize,
+}
+
+impl DisplayMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "freq" => Some(Self::Frequency),
+            "stats" => Some(Self::Statistics),
+            "duty" => Some(Self::Duty),
+            "period" => Some(Self::Period),
+            "rpm" => Some(Self::Tachometer),
+            "flow" => Some(Self::Flow),
+            "tune" => Some(Self::Tuner),
+            "lc" => Some(Self::Lc),
+            "mains" => Some(Self::Mains),
+            "rel" => Some(Self::Relative),
+            "total" => Some(Self::Totalize),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Frequency => "freq",
+            Self::Statistics => "stats",
+            Self::Duty => "duty",
+            Self::Period => "period",
+            Self::Tachometer => "rpm",
+            Self::Flow => "flow",
+            Self::Tuner => "tune",
+            Self::Lc => "lc",
+            Self::Mains => "mains",
+            Self::Relative => "rel",
+            Self::Totalize => "total",
+        }
+    }
+
+    /// The mode the mode button switches to.
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Frequency => Self::Relative,
+            Self::Relative => Self::Period,
+            Self::Period => Self::Tachometer,
+            Self::Tachometer => Self::Flow,
+            Self::Flow => Self::Tuner,
+            Self::Tuner => Self::Lc,
+            Self::Lc => Self::Mains,
+            Self::Mains => Self::Statistics,
+            Self::Statistics => Self::Duty,
+            Self::Duty => Self::Totalize,
+            Self::Totalize => Self::Frequency,
+        }
+    }
+}
+
+/// Builds a display line out of `parts`, padded with spaces to the full
+/// width so it overwrites whatever was there before. Anything that does not
+/// fit is cut.
+pub fn line(parts: &[&str]) -> String<16> {
+    let mut line = String::<16>::new();
+
+    for c in parts.iter().flat_map(|part| part.chars()) {
+        if line.push(c).is_err() {
+            break;
+        }
+    }
+
+    while line.len() < line.capacity() {
+        line.push(' ').unwrap();
+    }
+
+    line
+}
+
+/// Builds a display line with `label` on the left and `tag` on the right.
+pub fn tagged_line(label: &str, tag: &str) -> String<16> {
+    let mut line = line(&[label]);
+    let start = line.capacity().saturating_sub(tag.len());
+
+    line.truncate(start);
+    let _ = line.push_str(tag);
+
+    line
+}
+
+/// Label of the period reading, showing the external prescaler if any.
+pub fn period_label(prescaler_ratio: u16) -> String<16> {
+    if prescaler_ratio == 1 {
+        return line(&["Period:"]);
+    }
+
+    let ratio = format_utils::format_u32(prescaler_ratio.into());
+    line(&["Period /", ratio.as_str(), ":"])
+}
+
+/// Label of the frequency reading, showing the external prescaler if any.
+pub fn frequency_label(prescaler_ratio: u16) -> String<16> {
+    if prescaler_ratio == 1 {
+        return line(&["Frequency:"]);
+    }
+
+    let ratio = format_utils::format_u32(prescaler_ratio.into());
+    line(&["Freq /", ratio.as_str(), ":"])
+}
+
+/// Frequency and duty cycle on the first line, average high and low times on
+/// the second one.
+pub fn duty_lines(
+    freq: &str,
+    unit: &str,
+    pulses: PulseCapture,
+    cycles_per_micro: u64,
+) -> (String<16>, String<16>) {
+    // the widths get three digits, so that both fit on a single line
+    const WIDTH_SIGNIFICANT_DIGITS: u32 = 3;
+
+    let cycles = pulses.high_cycles.wrapping_add(pulses.low_cycles);
+    if pulses.periods == 0 || cycles == 0 {
+        return (line(&[" ", freq, " ", unit]), line(&[" ---"]));
+    }
+
+    let duty = FixedU64::<U8>::from(pulses.high_cycles) * 100 / FixedU64::<U8>::from(cycles);
+    let duty_str = format_utils::format_freq(duty, WIDTH_SIGNIFICANT_DIGITS);
+
+    let to_micros =
+        |width: u32| FixedU64::<U8>::from(width) / u64::from(pulses.periods) / cycles_per_micro;
+    let (high_str, high_unit) =
+        format_utils::format_time(to_micros(pulses.high_cycles), WIDTH_SIGNIFICANT_DIGITS);
+    let (low_str, low_unit) =
+        format_utils::format_time(to_micros(pulses.low_cycles), WIDTH_SIGNIFICANT_DIGITS);
+
+    (
+        line(&[" ", freq, " ", unit, " ", duty_str.as_str(), "%"]),
+        line(&[
+            "+",
+            high_str.as_str(),
+            high_unit,
+            " -",
+            low_str.as_str(),
+            low_unit,
+        ]),
+    )
+}
+
+/// The total, or what is left of the batch, with the state in the top right.
+pub fn totalizer_lines(totalizer: &Totalizer) -> (String<16>, String<16>) {
+    let state = if totalizer.preset_reached() {
+        "DONE"
+    } else if totalizer.is_running() {
+        "RUN"
+    } else {
+        "STOP"
+    };
+
+    match totalizer.remaining() {
+        Some(remaining) => (
+            tagged_line("Batch:", state),
+            line(&[" ", format_utils::format_u64(remaining).as_str(), " left"]),
+        ),
+        None => (
+            tagged_line("Total:", state),
+            line(&[" ", format_utils::format_u64(totalizer.total()).as_str()]),
+        ),
+    }
+}
+
+/// Flow rate with the method in the top right, dispensed volume below.
+pub fn flow_lines(rate: &str, tag: &str, volume_litres: FixedU64<U8>) -> (String<16>, String<16>) {
+    // the volume is good to the millilitre, or close enough
+    const VOLUME_SIGNIFICANT_DIGITS: u32 = 7;
+
+    let volume = format_utils::format_wide(volume_litres, VOLUME_SIGNIFICANT_DIGITS);
+
+    (
+        tagged_line(line(&[" ", rate, " L/min"]).as_str(), tag),
+        line(&[" ", volume.as_str(), " L"]),
+    )
+}
+
+/// Note and cents on the first line, a needle swinging over +/-50 cents on
+/// the second one.
+pub fn tuner_lines(note: Option<Note>) -> (String<16>, String<16>) {
+    let note = match note {
+        Some(note) => note,
+        None => return (line(&[" ---"]), line(&[])),
+    };
+
+    let mut cents = String::<16>::new();
+    let _ = cents.push(if note.cents < 0 { '-' } else { '+' });
+    let _ = cents.push_str(format_utils::format_u32(note.cents.unsigned_abs().into()).as_str());
+    let _ = cents.push_str(" ct");
+
+    let mut name = String::<16>::new();
+    let _ = name.push(' ');
+    let _ = name.push_str(note.name);
+    let _ = name.push(char::from(b'0' + note.octave));
+
+    // one pixel column per step, the middle column of the scale is 0 cents
+    let columns = NEEDLE_CELLS as i16 * 5 - 1;
+    let position = ((note.cents.clamp(-50, 50) + 50) * columns / 100) as usize;
+
+    let mut needle = String::<16>::new();
+    for cell in 0..NEEDLE_CELLS {
+        let c = if cell == position / 5 {
+            char::from(NEEDLE_SLOT + (position % 5) as u8)
+        } else if cell == NEEDLE_CELLS / 2 {
+            char::from(CENTER_TICK_SLOT)
+        } else {
+            ' '
+        };
+        let _ = needle.push(c);
+    }
+
+    (
+        tagged_line(name.as_str(), cents.as_str()),
+        line(&[needle.as_str()]),
+    )
+}
+
+/// The unknown component of the LC meter, `value` in farads or henries.
+pub fn lc_lines(meter: &LcMeter, value: Option<f32>, tag: &str) -> (String<16>, String<16>) {
+    // the oscillator drifts more than this anyway
+    const LC_SIGNIFICANT_DIGITS: u32 = 4;
+
+    let (label, units) = match meter.component() {
+        LcComponent::Capacitor => ("Cx:", [("pF", 1e12), ("nF", 1e9), ("uF", 1e6)]),
+        LcComponent::Inductor => ("Lx:", [("uH", 1e6), ("mH", 1e3), ("H", 1.0)]),
+    };
+
+    let second = match value {
+        _ if meter.is_pending() => line(&[" Measuring..."]),
+        _ if !meter.is_calibrated() => line(&[" Not calibrated"]),
+        None => line(&[" ---"]),
+        Some(value) => {
+            // the largest unit in which the value is at least one
+            let (symbol, scale) = units
+                .iter()
+                .rev()
+                .find(|(_, scale)| value * scale >= 1.0)
+                .unwrap_or(&units[0]);
+
+            // a component smaller than the drift can come out negative
+            let scaled = FixedU64::<U8>::saturating_from_num(value * scale);
+            let value_str = format_utils::format_freq(scaled, LC_SIGNIFICANT_DIGITS);
+
+            line(&[" ", value_str.as_str(), " ", *symbol])
+        }
+    };
+
+    (tagged_line(label, tag), second)
+}
+
+/// Grid frequency on the first line, with ALM in the corner when out of the
+/// band. The second line alternates between the deviation and its min/max.
+pub fn mains_lines(mains: &MainsMonitor, tag: &str, show_range: bool) -> (String<16>, String<16>) {
+    // a reading over 50 cycles is good to well under the mHz it is shown in
+    const MAINS_SIGNIFICANT_DIGITS: u32 = 5;
+
+    let tag = if mains.is_alarm() { "ALM" } else { tag };
+
+    let hz = match mains.hz() {
+        Some(hz) => hz,
+        None => return (tagged_line(" ---", tag), line(&[" Measuring..."])),
+    };
+    let hz_str = format_utils::format_scaled(hz, MAINS_SIGNIFICANT_DIGITS);
+    let first = tagged_line(line(&[" ", hz_str.as_str(), " Hz"]).as_str(), tag);
+
+    let second = match (show_range, mains.min_mhz(), mains.max_mhz()) {
+        (true, Some(min), Some(max)) => line(&[
+            " ",
+            format_utils::format_signed(min).as_str(),
+            "/",
+            format_utils::format_signed(max).as_str(),
+            " mHz",
+        ]),
+        _ => {
+            let deviation = mains.deviation_mhz().unwrap_or(0);
+            line(&[
+                " d ",
+                format_utils::format_signed(deviation).as_str(),
+                " mHz",
+            ])
+        }
+    };
+
+    (first, second)
+}
+
+/// Deviation from the nominal in Hz on the first line, in ppm on the second.
+pub fn relative_lines(
+    deviation_hz: Option<FixedI64<U8>>,
+    deviation_ppm: Option<FixedI64<U8>>,
+    tag: &str,
+) -> (String<16>, String<16>) {
+    // the deviation is what is left after the nominal, so few digits are
+    // meaningful, at most the ones of the reading
+    const DEVIATION_SIGNIFICANT_DIGITS: u32 = 4;
+    const PPM_SIGNIFICANT_DIGITS: u32 = 6;
+
+    let (deviation_hz, deviation_ppm) = match (deviation_hz, deviation_ppm) {
+        (Some(hz), Some(ppm)) => (hz, ppm),
+        _ => return (tagged_line("Relative:", tag), line(&[" No nominal set"])),
+    };
+
+    let sign = |value: FixedI64<U8>| if value < 0 { " -" } else { " +" };
+
+    let (hz_str, hz_unit) =
+        format_utils::format_hz(deviation_hz.unsigned_abs(), DEVIATION_SIGNIFICANT_DIGITS);
+    let ppm_str = format_utils::format_wide(deviation_ppm.unsigned_abs(), PPM_SIGNIFICANT_DIGITS);
+
+    (
+        tagged_line(
+            line(&[sign(deviation_hz), hz_str.as_str(), " ", hz_unit]).as_str(),
+            tag,
+        ),
+        line(&[sign(deviation_ppm), ppm_str.as_str(), " ppm"]),
+    )
+}
+
+/// Tag of a reading checked against the limits, `None` if there are none.
+pub fn limit_tag(limits: &Limits) -> Option<&'static str> {
+    if !limits.is_enabled() {
+        return None;
+    }
+
+    Some(match limits.state() {
+        LimitState::Pass => "OK",
+        LimitState::Under => "<LO",
+        LimitState::Over => ">HI",
+    })
+}
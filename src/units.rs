@@ -0,0 +1,227 @@
+use fixed::{types::extra::U8, FixedU64};
+
+/// A value in some unit, as an integer and the number of decimals in it.
+///
+/// e.g. `Scaled { value: 5001, decimals: 3 }` is 5.001. Scaling to the larger
+/// units in fixed point would leave the small decimals to the 8 fractional
+/// bits, which only resolve 1/256.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Scaled {
+    pub value: u64,
+    pub decimals: u32,
+}
+
+impl Scaled {
+    /// The three decimals the display shows at most.
+    const fn milli(value: u64) -> Self {
+        Self { value, decimals: 3 }
+    }
+
+    /// A fixed point value, with the two decimals its fraction can resolve.
+    pub fn from_fixed(value: FixedU64<U8>) -> Self {
+        Self {
+            value: (value * 100).to_num(),
+            decimals: 2,
+        }
+    }
+
+    /// The integer part.
+    pub fn integer(self) -> u64 {
+        self.value / 10_u64.pow(self.decimals)
+    }
+}
+
+/// SI prefixed units of frequency.
+///
+/// GHz is only reachable with an external prescaler in front of the input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unit {
+    MilliHertz,
+    Hertz,
+    KiloHertz,
+    MegaHertz,
+    GigaHertz,
+}
+
+impl Unit {
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::MilliHertz => "mHz",
+            Self::Hertz => "Hz",
+            Self::KiloHertz => "kHz",
+            Self::MegaHertz => "MHz",
+            Self::GigaHertz => "GHz",
+        }
+    }
+
+    /// The largest unit in which `hz` is at least one.
+    pub fn for_hz(hz: FixedU64<U8>) -> Self {
+        if hz >= 1_000_000_000 {
+            Self::GigaHertz
+        } else if hz >= 1_000_000 {
+            Self::MegaHertz
+        } else if hz >= 1_000 {
+            Self::KiloHertz
+        } else if hz >= 1 {
+            Self::Hertz
+        } else {
+            Self::MilliHertz
+        }
+    }
+
+    /// Converts a frequency in Hz to this unit.
+    ///
+    /// Only keeps the decimals the 1/256 Hz resolution of `hz` is good for.
+    pub fn scale_hz(self, hz: FixedU64<U8>) -> Scaled {
+        let bits = hz.to_bits();
+
+        match self {
+            Self::MilliHertz => Scaled {
+                value: (bits * 1_000) >> 8,
+                decimals: 0,
+            },
+            Self::Hertz => Scaled::from_fixed(hz),
+            Self::KiloHertz => Scaled::milli(bits >> 8),
+            Self::MegaHertz => Scaled::milli((bits >> 8) / 1_000),
+            Self::GigaHertz => Scaled::milli((bits >> 8) / 1_000_000),
+        }
+    }
+
+    /// Frequency of `counts` edges in `interval_micros`, in this unit.
+    ///
+    /// Better than going through `scale_hz`, as the counts are scaled up
+    /// before the division and we do not lose the small units' decimals.
+    pub fn scale_counts(self, counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> Scaled {
+        // both have 8 fractional bits, so their ratio is that of the raw bits
+        let (counts, interval) = (counts.to_bits(), interval_micros.to_bits());
+
+        Scaled::milli(match self {
+            Self::MilliHertz => counts * 1_000_000_000_000 / interval,
+            Self::Hertz => counts * 1_000_000_000 / interval,
+            Self::KiloHertz => counts * 1_000_000 / interval,
+            Self::MegaHertz => counts * 1_000 / interval,
+            Self::GigaHertz => counts / interval,
+        })
+    }
+}
+
+/// Picks the unit of the readings, with hysteresis.
+///
+/// We move to a larger unit as soon as the value reaches 1000, but only go
+/// back to a smaller one when the value drops under 0.9, so a signal sitting
+/// right at a boundary does not flicker between e.g. "999.9 Hz" and
+/// "1.000 kHz".
+pub struct UnitSelector {
+    unit: Unit,
+}
+
+impl Default for UnitSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnitSelector {
+    pub const fn new() -> Self {
+        Self { unit: Unit::Hertz }
+    }
+
+    pub fn select(&mut self, hz: FixedU64<U8>) -> Unit {
+        let value = self.unit.scale_hz(hz);
+        let one = 10_u64.pow(value.decimals);
+
+        if value.value >= 1_000 * one || value.value * 10 < 9 * one {
+            self.unit = Unit::for_hz(hz);
+        }
+
+        self.unit
+    }
+}
+
+/// SI prefixed units of time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TimeUnit {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl TimeUnit {
+    /// The LCD character ROM has no µ we can reach from UTF-8, so "us" it is.
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Nanoseconds => "ns",
+            Self::Microseconds => "us",
+            Self::Milliseconds => "ms",
+            Self::Seconds => "s",
+        }
+    }
+
+    /// The largest unit in which `micros` is at least one.
+    pub fn for_micros(micros: FixedU64<U8>) -> Self {
+        if micros >= 1_000_000 {
+            Self::Seconds
+        } else if micros >= 1_000 {
+            Self::Milliseconds
+        } else if micros >= 1 {
+            Self::Microseconds
+        } else {
+            Self::Nanoseconds
+        }
+    }
+
+    /// Converts a time in microseconds to this unit.
+    pub fn scale_micros(self, micros: FixedU64<U8>) -> FixedU64<U8> {
+        match self {
+            Self::Nanoseconds => micros * 1_000,
+            Self::Microseconds => micros,
+            Self::Milliseconds => micros / 1_000,
+            Self::Seconds => micros / 1_000_000,
+        }
+    }
+
+    /// Period of `counts` edges in `interval_micros`, in this unit.
+    ///
+    /// Same as `Unit::scale_counts`, the interval is scaled up before the
+    /// division so the nanoseconds keep their decimals.
+    pub fn scale_period(self, counts: FixedU64<U8>, interval_micros: FixedU64<U8>) -> Scaled {
+        let (counts, interval) = (counts.to_bits(), interval_micros.to_bits());
+
+        Scaled::milli(match self {
+            Self::Nanoseconds => interval * 1_000_000 / counts,
+            Self::Microseconds => interval * 1_000 / counts,
+            Self::Milliseconds => interval / counts,
+            Self::Seconds => interval / (counts * 1_000),
+        })
+    }
+}
+
+/// Picks the unit of the periods, with the same hysteresis as `UnitSelector`.
+pub struct TimeUnitSelector {
+    unit: TimeUnit,
+}
+
+impl Default for TimeUnitSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeUnitSelector {
+    pub const fn new() -> Self {
+        Self {
+            unit: TimeUnit::Microseconds,
+        }
+    }
+
+    pub fn select(&mut self, micros: FixedU64<U8>) -> TimeUnit {
+        let value = self.unit.scale_micros(micros);
+
+        if value >= 1_000 || value * 10 < 9 {
+            self.unit = TimeUnit::for_micros(micros);
+        }
+
+        self.unit
+    }
+}